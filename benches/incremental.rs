@@ -0,0 +1,84 @@
+//! Rebuilding a 10k-line file after an edit, incrementally from the edited
+//! syntax tree and from scratch.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lever_core::{File, LanguageDefinition, LanguageRegistry, LanguageSetup, RulesTranslator};
+use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent, Url};
+
+const RULES: &str = include_str!("../tests/fixtures/javascript.ron");
+
+fn source_code() -> String {
+    (0..1000)
+        .map(|i| {
+            format!(
+                "function f{i}(first, second) {{
+    let value{i} = {{ key: first }};
+    let copy = value{i};
+    f{i}(arg, second);
+    return copy;
+}}
+let g{i} = 1;
+f{i}(arg);
+
+
+"
+            )
+        })
+        .collect()
+}
+
+fn open_file() -> (File, LanguageRegistry) {
+    let (language_def, _) =
+        LanguageDefinition::load(RULES, tree_sitter_javascript::language()).unwrap();
+    let mut registry = LanguageRegistry::default();
+    registry
+        .register(&LanguageSetup {
+            language_def: RULES.to_string(),
+            rules_path: None,
+            treesitter_language: tree_sitter_javascript::language(),
+            translator: Arc::new(RulesTranslator::new(Arc::new(language_def))),
+        })
+        .unwrap();
+
+    let uri = Url::parse("file:///bench.js").unwrap();
+    let file = File::new(uri, &source_code(), &registry.languages()[0]);
+    (file, registry)
+}
+
+/// Types a character in the middle of the file, then removes it.
+fn edit(file: &mut File) {
+    let start = Position::new(5000, 4);
+    let end = Position::new(5000, 5);
+
+    file.update(vec![TextDocumentContentChangeEvent {
+        range: Some(Range::new(start, start)),
+        range_length: None,
+        text: String::from("z"),
+    }]);
+    file.update(vec![TextDocumentContentChangeEvent {
+        range: Some(Range::new(start, end)),
+        range_length: None,
+        text: String::new(),
+    }]);
+}
+
+fn bench_update(c: &mut Criterion) {
+    let (mut file, registry) = open_file();
+
+    c.bench_function("update 10k lines", |b| b.iter(|| edit(&mut file)));
+    c.bench_function("rebuild 10k lines", |b| {
+        b.iter(|| {
+            file.rebuild(&registry.languages()[0]);
+            file.rebuild(&registry.languages()[0]);
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = bench_update
+}
+criterion_main!(benches);
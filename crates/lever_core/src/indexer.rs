@@ -0,0 +1,212 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use ignore::WalkBuilder;
+use tokio::sync::mpsc;
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, Url, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
+};
+use tower_lsp::Client;
+
+use crate::language_def::LanguageRegistry;
+use crate::project::workspace::Workspace;
+use crate::project::File;
+
+// Folders added while indexing are indexed at the same time, so each run has
+// its own progress token
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+// Files added to the workspace under one write lock, requests are answered
+// between the batches
+const BATCH_SIZE: usize = 64;
+
+/// Crawls the folders of the workspace for files of the registered languages
+/// and adds them to the workspace, so that workspace symbols and references
+/// also cover files that were never opened. Files are parsed in parallel and
+/// the progress is reported to the client when it supports it.
+pub struct WorkspaceIndexer {
+    roots: Vec<PathBuf>,
+    registry: Arc<LanguageRegistry>,
+    client: Client,
+    workspace: Arc<RwLock<Workspace>>,
+    report_progress: bool,
+}
+
+impl WorkspaceIndexer {
+    pub fn spawn(
+        roots: Vec<PathBuf>,
+        registry: Arc<LanguageRegistry>,
+        client: Client,
+        workspace: Arc<RwLock<Workspace>>,
+        report_progress: bool,
+    ) {
+        if roots.is_empty() {
+            return;
+        }
+
+        let indexer = WorkspaceIndexer {
+            roots,
+            registry,
+            client,
+            workspace,
+            report_progress,
+        };
+
+        tokio::spawn(indexer.run());
+    }
+
+    async fn run(self) {
+        info!("Indexing workspace: {:?}", self.roots);
+        let token = NumberOrString::String(format!(
+            "lever-indexing-{}",
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let report_progress = self.report_progress
+            && self
+                .client
+                .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                    token: token.clone(),
+                })
+                .await
+                .is_ok();
+
+        if report_progress {
+            self.notify_progress(
+                &token,
+                WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: String::from("Indexing workspace"),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(0),
+                }),
+            )
+            .await;
+        }
+
+        let roots = self.roots.clone();
+        let registry = self.registry.clone();
+        let paths = tokio::task::spawn_blocking(move || crawl(&roots, &registry))
+            .await
+            .unwrap_or_default();
+        let total = paths.len();
+
+        let workers = thread::available_parallelism().map_or(1, usize::from);
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Option<File>>();
+        for chunk in paths.chunks(total.div_ceil(workers).max(1)) {
+            let chunk = chunk.to_vec();
+            let registry = self.registry.clone();
+            let sender = sender.clone();
+            tokio::task::spawn_blocking(move || {
+                for path in chunk {
+                    if sender.send(parse(path, &registry)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut batch = vec![];
+        let mut added = 0;
+        let mut indexed = 0;
+        let mut last_percentage = 0;
+        while let Some(file) = receiver.recv().await {
+            batch.extend(file);
+            indexed += 1;
+
+            // Imports are read from disk when they are not added yet, so the
+            // files of a batch do not wait for the next ones
+            if batch.len() == BATCH_SIZE {
+                added += batch.len();
+                self.add_files(std::mem::take(&mut batch));
+            }
+
+            let percentage = (indexed * 100 / total) as u32;
+            if report_progress && percentage != last_percentage {
+                last_percentage = percentage;
+                self.notify_progress(
+                    &token,
+                    WorkDoneProgress::Report(WorkDoneProgressReport {
+                        cancellable: Some(false),
+                        message: Some(format!("{indexed}/{total} files")),
+                        percentage: Some(percentage),
+                    }),
+                )
+                .await;
+            }
+        }
+
+        added += batch.len();
+        self.add_files(batch);
+        info!("Workspace indexed: {added} files");
+
+        if report_progress {
+            self.notify_progress(
+                &token,
+                WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: Some(format!("{added} files indexed")),
+                }),
+            )
+            .await;
+        }
+    }
+
+    fn add_files(&self, files: Vec<File>) {
+        if !files.is_empty() {
+            self.workspace.write().unwrap().add_workspace_files(files);
+        }
+    }
+
+    async fn notify_progress(&self, token: &NumberOrString, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+}
+
+/// Files of the registered languages in the roots, skipping hidden files and
+/// the ones excluded by `.gitignore` or `.ignore` files.
+fn crawl(roots: &[PathBuf], registry: &LanguageRegistry) -> Vec<PathBuf> {
+    let mut walker = WalkBuilder::new(&roots[0]);
+    for root in &roots[1..] {
+        walker.add(root);
+    }
+    walker.require_git(false);
+
+    // A set, since roots may be nested
+    let mut paths = BTreeSet::new();
+    for entry in walker.build() {
+        match entry {
+            Ok(entry) => {
+                let path = entry.into_path();
+                if path.is_file() && registry.matches_path(&path) {
+                    paths.insert(path);
+                }
+            }
+            Err(error) => warn!("Failed to index: {error}"),
+        }
+    }
+
+    paths.into_iter().collect()
+}
+
+fn parse(path: PathBuf, registry: &LanguageRegistry) -> Option<File> {
+    let url = Url::from_file_path(&path).ok()?;
+    let language = registry.get_for_path(&path)?;
+    let content = fs::read_to_string(&path)
+        .map_err(|error| warn!("Failed to read {}: {error}", path.display()))
+        .ok()?;
+
+    Some(File::new(url, &content, &language))
+}
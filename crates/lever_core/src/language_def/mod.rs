@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use itertools::Itertools;
+use serde::Deserialize;
+use tower_lsp::lsp_types;
+
+use crate::lsp_mappings::{HighlightType, SymbolCompletionType, SymbolKindType};
+use crate::project::NodeKind;
+
+mod registry;
+mod validation;
+
+pub use registry::{LanguageEntry, LanguageRegistry};
+pub use validation::{RulesDiagnostic, RulesLocation, Severity, ValidationReport};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Rule {
+    pub node_name: String,
+    #[serde(default)]
+    pub symbol: Symbol,
+    #[serde(default)]
+    pub is_scope: bool,
+    /// Lets symbols of this rule share a name with other symbols of the same
+    /// type and scope defined by rules that also allow it.
+    #[serde(default)]
+    pub allow_overloading: bool,
+    #[serde(default)]
+    pub import: Import,
+    #[serde(default)]
+    pub children: Vec<Child>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Child {
+    pub query: TreesitterNodeQuery,
+    pub rule: DirectOrRule,
+    pub highlight_type: Option<HighlightType>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Clone)]
+pub enum TreesitterNodeQuery {
+    Path(Vec<TreesitterNodeQuery>),
+    Kind(String),
+    Field(String),
+    /// Tree-sitter query pattern matched anywhere below the current node.
+    /// Every node captured by a name that does not start with `_` is
+    /// translated with the child's rule.
+    Query(String),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum DirectOrRule {
+    Direct(String),
+    Rule(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Default)]
+pub enum Symbol {
+    Init {
+        #[serde(rename(deserialize = "type"))]
+        kind: String,
+        name_node: String,
+        type_node: Option<String>,
+    },
+    Usage,
+    Field {
+        name_node: String,
+    },
+    Expression,
+    MemberUsage,
+    #[default]
+    None,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Default)]
+pub enum Import {
+    Local,
+    Library,
+    #[default]
+    None,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SymbolDef {
+    pub name: String,
+    pub completion_type: SymbolCompletionType,
+    pub highlight_type: HighlightType,
+    /// Kind shown in outlines, derived from `completion_type` when unset.
+    #[serde(default)]
+    pub symbol_kind: Option<SymbolKindType>,
+    /// Whether definitions of this type without usages are reported.
+    #[serde(default)]
+    pub unused: UnusedPolicy,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy, Default)]
+pub enum UnusedPolicy {
+    Report,
+    /// Only nested definitions are reported, top-level ones may be used by
+    /// files importing this one, e.g. exported messages.
+    #[default]
+    AllowTopLevel,
+    Allow,
+}
+
+impl SymbolDef {
+    pub fn get_symbol_kind(&self) -> lsp_types::SymbolKind {
+        self.symbol_kind.as_ref().map_or_else(
+            || self.completion_type.get_symbol_kind(),
+            SymbolKindType::get,
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Language {
+    pub name: String,
+    pub file_extensions: Vec<String>,
+    pub library_paths: LibraryPaths,
+    /// Reports import cycles as errors instead of warnings
+    #[serde(default)]
+    pub forbid_cyclic_imports: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LibraryPaths {
+    pub env_variables: Vec<String>,
+    pub linux: Vec<PathBuf>,
+    pub windows: Vec<PathBuf>,
+    pub macos: Vec<PathBuf>,
+}
+
+impl LibraryPaths {
+    /// Directories searched for library imports on this platform, those of
+    /// the environment variables first.
+    pub fn directories(&self) -> Vec<PathBuf> {
+        let mut directories: Vec<PathBuf> = self
+            .env_variables
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .map(PathBuf::from)
+            .collect();
+
+        if cfg!(target_os = "windows") {
+            directories.extend(self.windows.iter().cloned());
+        } else if cfg!(target_os = "macos") {
+            directories.extend(self.macos.iter().cloned());
+        } else if cfg!(target_os = "linux") {
+            directories.extend(self.linux.iter().cloned());
+        } else {
+            error!("Unsupported platform for imports, all file imports will fail.");
+        }
+
+        directories
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LanguageDefinition {
+    pub language: Language,
+    pub keywords: Vec<String>,
+    pub symbol_types: Vec<SymbolDef>,
+    pub global_ast_rules: Vec<Child>,
+    pub ast_rules: Vec<Rule>,
+    #[serde(skip)]
+    scope_nodes: Vec<NodeKind>,
+    #[serde(skip)]
+    keyword_set: HashSet<String>,
+    // Compiled `Query` patterns, filled by `load`
+    #[serde(skip)]
+    queries: HashMap<String, Arc<tree_sitter::Query>>,
+}
+
+impl LanguageDefinition {
+    /// Parses a rules file, only reporting RON syntax and shape errors.
+    /// Use `validate` to check the references between rules.
+    pub fn parse(language_definition: &str) -> Result<LanguageDefinition, ValidationReport> {
+        let language_def_modified = format!(
+            "#![enable(unwrap_variant_newtypes)]\n#![enable(implicit_some)]\n{language_definition}"
+        );
+
+        let mut language_def: LanguageDefinition = ron::de::from_str(&language_def_modified)?;
+
+        language_def.scope_nodes = language_def
+            .ast_rules
+            .iter()
+            .filter(|rule| rule.is_scope)
+            .map(|rule| NodeKind::Node(rule.node_name.clone()))
+            .collect();
+        language_def.keyword_set = HashSet::from_iter(language_def.keywords.clone());
+
+        Ok(language_def)
+    }
+
+    /// Parses and validates a rules file, and compiles its queries for
+    /// `treesitter_language`. The returned report contains the warnings on
+    /// success and every problem on failure.
+    pub fn load(
+        language_definition: &str,
+        treesitter_language: tree_sitter::Language,
+    ) -> Result<(LanguageDefinition, ValidationReport), ValidationReport> {
+        let mut language_def = LanguageDefinition::parse(language_definition)?;
+
+        let mut report = language_def.validate(language_definition);
+        report.diagnostics.extend(
+            language_def
+                .compile_queries(treesitter_language, language_definition)
+                .diagnostics,
+        );
+        if report.has_errors() {
+            return Err(report);
+        }
+
+        Ok((language_def, report))
+    }
+
+    pub(crate) fn init_semanc_token_types(&self) -> Vec<lsp_types::SemanticTokenType> {
+        let mut symbol_types = self
+            .symbol_types
+            .iter()
+            .map(|s| s.highlight_type.get())
+            .collect::<Vec<lsp_types::SemanticTokenType>>();
+        symbol_types.insert(0, HighlightType::Keyword.get());
+
+        for rule in &self.ast_rules {
+            for child in &rule.children {
+                if let Some(semantic_token_type) = &child.highlight_type {
+                    symbol_types.push(semantic_token_type.get());
+                }
+            }
+        }
+
+        for child in &self.global_ast_rules {
+            if let Some(semantic_token_type) = &child.highlight_type {
+                symbol_types.push(semantic_token_type.get());
+            }
+        }
+
+        symbol_types.into_iter().unique().collect_vec()
+    }
+
+    pub fn rule_with_name(&self, name: &str) -> Option<&Rule> {
+        self.ast_rules.iter().find(|rule| rule.node_name == name)
+    }
+
+    pub fn scope_nodes(&self) -> &Vec<NodeKind> {
+        &self.scope_nodes
+    }
+
+    pub fn keyword_set(&self) -> &HashSet<String> {
+        &self.keyword_set
+    }
+
+    /// Compiled pattern of a `Query`, only available on definitions returned
+    /// by `load`.
+    pub fn get_query(&self, pattern: &str) -> Option<Arc<tree_sitter::Query>> {
+        self.queries.get(pattern).cloned()
+    }
+
+    pub fn is_scope_node(&self, kind: &NodeKind) -> bool {
+        self.scope_nodes.contains(kind)
+    }
+
+    pub fn get_symbol_def(&self, symbol_kind: &str) -> Option<&SymbolDef> {
+        self.symbol_types
+            .iter()
+            .find(|symbol_type| symbol_type.name == symbol_kind)
+    }
+
+    /// Whether symbols of a type are members of another symbol, see
+    /// `SymbolCompletionType::is_member`.
+    pub fn is_member_symbol(&self, symbol_kind: &str) -> bool {
+        self.get_symbol_def(symbol_kind)
+            .is_some_and(|symbol_def| symbol_def.completion_type.is_member())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use tower_lsp::lsp_types::{SemanticTokenType, SemanticTokensLegend, Url};
+
+use super::{LanguageDefinition, RulesDiagnostic, Severity, ValidationReport};
+use crate::setup::LanguageSetup;
+use crate::{RulesTranslator, Translator};
+
+pub struct LanguageEntry {
+    pub treesitter_language: tree_sitter::Language,
+    pub rules_path: Option<PathBuf>,
+    // Generated from `embedded_rules`, so only valid for those rules
+    translator: Arc<dyn Translator>,
+    embedded_rules: String,
+    active: RwLock<ActiveRules>,
+}
+
+struct ActiveRules {
+    language_def: Arc<LanguageDefinition>,
+    translator: Arc<dyn Translator>,
+    // Rules the definition was loaded from
+    source: String,
+}
+
+impl LanguageEntry {
+    /// Current definition, files keep the one they were built with until
+    /// they are rebuilt.
+    pub fn definition(&self) -> Arc<LanguageDefinition> {
+        self.active.read().unwrap().language_def.clone()
+    }
+
+    /// Current definition with the translator that builds its ASTs.
+    pub fn current(&self) -> (Arc<LanguageDefinition>, Arc<dyn Translator>) {
+        let active = self.active.read().unwrap();
+        (active.language_def.clone(), active.translator.clone())
+    }
+
+    /// Rules the current definition was loaded from.
+    pub fn source(&self) -> String {
+        self.active.read().unwrap().source.clone()
+    }
+
+    fn activate(&self, language_def: LanguageDefinition, source: &str) {
+        *self.active.write().unwrap() = ActiveRules::new(
+            language_def,
+            source,
+            source == self.embedded_rules,
+            &self.translator,
+        );
+    }
+}
+
+impl ActiveRules {
+    fn new(
+        language_def: LanguageDefinition,
+        source: &str,
+        is_embedded: bool,
+        translator: &Arc<dyn Translator>,
+    ) -> ActiveRules {
+        let language_def = Arc::new(language_def);
+        let translator = if is_embedded {
+            translator.clone()
+        } else {
+            Arc::new(RulesTranslator::new(language_def.clone()))
+        };
+
+        ActiveRules {
+            language_def,
+            translator,
+            source: source.to_string(),
+        }
+    }
+}
+
+/// Languages hosted by the server, keyed by their file extensions.
+#[derive(Default)]
+pub struct LanguageRegistry {
+    languages: Vec<Arc<LanguageEntry>>,
+    extensions: HashMap<String, usize>,
+    // Sent once to the client, so it only grows while registering languages
+    semantic_token_types: Vec<SemanticTokenType>,
+}
+
+impl LanguageRegistry {
+    pub fn register(
+        &mut self,
+        setup: &LanguageSetup,
+    ) -> Result<ValidationReport, ValidationReport> {
+        let source = setup.read_rules();
+        let (language_def, mut report) =
+            LanguageDefinition::load(&source, setup.treesitter_language)?;
+
+        let index = self.languages.len();
+        for extension in &language_def.language.file_extensions {
+            let extension = extension.trim_start_matches('.').to_string();
+            if let Some(other) = self.extensions.get(&extension) {
+                report.diagnostics.push(RulesDiagnostic {
+                    severity: Severity::Warning,
+                    message: format!(
+                        "extension `{extension}` is already used by language `{}`",
+                        self.languages[*other].definition().language.name
+                    ),
+                    location: None,
+                });
+            } else {
+                self.extensions.insert(extension, index);
+            }
+        }
+
+        for token_type in language_def.init_semanc_token_types() {
+            if !self.semantic_token_types.contains(&token_type) {
+                self.semantic_token_types.push(token_type);
+            }
+        }
+
+        let is_embedded = source == setup.language_def;
+        if !is_embedded {
+            info!("Rules differ from the embedded ones, they will be interpreted");
+        }
+
+        self.languages.push(Arc::new(LanguageEntry {
+            treesitter_language: setup.treesitter_language,
+            rules_path: setup.rules_path.clone(),
+            active: RwLock::new(ActiveRules::new(
+                language_def,
+                &source,
+                is_embedded,
+                &setup.translator,
+            )),
+            translator: setup.translator.clone(),
+            embedded_rules: setup.language_def.clone(),
+        }));
+
+        Ok(report)
+    }
+
+    /// Validates new rules for a registered language and swaps its definition.
+    /// On failure, the current definition stays active.
+    pub fn reload(&self, index: usize, source: &str) -> Result<ValidationReport, ValidationReport> {
+        let (language_def, mut report) =
+            LanguageDefinition::load(source, self.languages[index].treesitter_language)?;
+
+        for token_type in language_def
+            .init_semanc_token_types()
+            .iter()
+            .filter(|t| !self.semantic_token_types.contains(t))
+        {
+            report.diagnostics.push(RulesDiagnostic {
+                severity: Severity::Warning,
+                message: format!(
+                    "highlight type `{}` was not used when the server started, restart the server to apply it",
+                    token_type.as_str()
+                ),
+                location: None,
+            });
+        }
+
+        self.languages[index].activate(language_def, source);
+
+        Ok(report)
+    }
+
+    pub fn languages(&self) -> &[Arc<LanguageEntry>] {
+        &self.languages
+    }
+
+    /// Language registered for the extension of the path, files of other
+    /// extensions are not handled by the server.
+    pub fn get_for_path(&self, path: &Path) -> Option<Arc<LanguageEntry>> {
+        let index = path
+            .extension()
+            .and_then(|extension| self.extensions.get(extension.to_str()?));
+        if index.is_none() {
+            debug!("No language registered for {}", path.display());
+        }
+
+        self.languages.get(*index?).cloned()
+    }
+
+    /// Whether a language is registered for the extension of the path.
+    pub fn matches_path(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| self.extensions.contains_key(extension))
+    }
+
+    pub fn get_for_url(&self, url: &Url) -> Option<Arc<LanguageEntry>> {
+        self.get_for_path(Path::new(url.path()))
+    }
+
+    pub fn get_semantic_token_types(&self) -> &[SemanticTokenType] {
+        &self.semantic_token_types
+    }
+
+    pub fn get_semantic_token_legend(&self) -> SemanticTokensLegend {
+        SemanticTokensLegend {
+            token_types: self.semantic_token_types.clone(),
+            token_modifiers: vec![],
+        }
+    }
+
+    /// Used to name the log file.
+    pub fn get_name(&self) -> String {
+        self.languages
+            .iter()
+            .map(|language| language.definition().language.name.to_lowercase())
+            .collect::<Vec<String>>()
+            .join("-")
+    }
+}
@@ -0,0 +1,670 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use tower_lsp::lsp_types::{self, Diagnostic, DiagnosticSeverity, Position};
+
+use super::{Child, DirectOrRule, LanguageDefinition, Rule, Symbol, TreesitterNodeQuery};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// 1-based line and column inside the rules file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RulesLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RulesDiagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<RulesLocation>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub diagnostics: Vec<RulesDiagnostic>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &RulesDiagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diag| diag.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &RulesDiagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diag| diag.severity == Severity::Warning)
+    }
+
+    /// Converts the report to LSP diagnostics on the rules file, each
+    /// spanning from its location to the end of the line.
+    pub fn to_diagnostics(&self, source: &str) -> Vec<Diagnostic> {
+        let lines: Vec<&str> = source.lines().collect();
+
+        self.diagnostics
+            .iter()
+            .map(|diag| {
+                let (line, column) = diag.location.map_or((0, 0), |l| (l.line - 1, l.column - 1));
+                let line_length = lines.get(line).map_or(0, |l| l.chars().count());
+
+                Diagnostic::new(
+                    lsp_types::Range::new(
+                        Position::new(line as u32, column as u32),
+                        Position::new(line as u32, line_length.max(column + 1) as u32),
+                    ),
+                    Some(match diag.severity {
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                    }),
+                    None,
+                    Some("Lever rules".to_string()),
+                    diag.message.clone(),
+                    None,
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    fn push(&mut self, severity: Severity, message: String, location: Option<RulesLocation>) {
+        self.diagnostics.push(RulesDiagnostic {
+            severity,
+            message,
+            location,
+        });
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+impl fmt::Display for RulesDiagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(
+                fmt,
+                "{} at {}:{}: {}",
+                self.severity, location.line, location.column, self.message
+            ),
+            None => write!(fmt, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for diag in &self.diagnostics {
+            writeln!(fmt, "{diag}")?;
+        }
+
+        let errors = self.errors().count();
+        let warnings = self.warnings().count();
+        write!(fmt, "{errors} error(s), {warnings} warning(s)")
+    }
+}
+
+impl From<ron::error::SpannedError> for ValidationReport {
+    fn from(error: ron::error::SpannedError) -> Self {
+        let mut report = ValidationReport::default();
+
+        // Two extension lines are prepended before parsing, see `LanguageDefinition::parse`
+        let location = RulesLocation {
+            line: error.position.line.saturating_sub(2).max(1),
+            column: error.position.col,
+        };
+        report.push(Severity::Error, error.code.to_string(), Some(location));
+
+        report
+    }
+}
+
+/// Finds approximate positions of rule elements in the raw RON source, since
+/// the deserialized definition does not keep any span information.
+struct Locator<'a> {
+    source: &'a str,
+    rule_offsets: Vec<Option<usize>>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(source: &'a str, rules: &[Rule]) -> Locator<'a> {
+        let mut rule_offsets = Vec::with_capacity(rules.len());
+        let mut start = 0;
+
+        for rule in rules {
+            let offset = Self::find_key_value(source, start, "node_name", &rule.node_name);
+            if let Some(offset) = offset {
+                start = offset + 1;
+            }
+            rule_offsets.push(offset);
+        }
+
+        Locator {
+            source,
+            rule_offsets,
+        }
+    }
+
+    fn find_key_value(source: &str, start: usize, key: &str, value: &str) -> Option<usize> {
+        let needle = format!("\"{value}\"");
+        let mut search_start = start;
+
+        while let Some(found) = source.get(search_start..)?.find(&needle) {
+            let offset = search_start + found;
+            let before = source[..offset].trim_end();
+            if let Some(before) = before.strip_suffix(':') {
+                if before.trim_end().ends_with(key) {
+                    return Some(offset);
+                }
+            }
+            search_start = offset + needle.len();
+        }
+
+        None
+    }
+
+    fn rule_span(&self, rule_index: usize) -> (usize, usize) {
+        let start = self.rule_offsets[rule_index].unwrap_or(0);
+        let end = self.rule_offsets[rule_index + 1..]
+            .iter()
+            .find_map(|offset| *offset)
+            .unwrap_or(self.source.len());
+
+        (start, end)
+    }
+
+    fn rule(&self, rule_index: usize) -> Option<RulesLocation> {
+        self.rule_offsets[rule_index].map(|offset| self.location(offset))
+    }
+
+    fn in_rule(&self, rule_index: usize, needle: &str) -> Option<RulesLocation> {
+        let (start, end) = self.rule_span(rule_index);
+        self.source[start..end]
+            .find(needle)
+            .map(|found| self.location(start + found))
+            .or_else(|| self.rule(rule_index))
+    }
+
+    fn key_value_in_rule(
+        &self,
+        rule_index: usize,
+        key: &str,
+        value: &str,
+    ) -> Option<RulesLocation> {
+        let (start, end) = self.rule_span(rule_index);
+        Self::find_key_value(self.source, start, key, value)
+            .filter(|offset| *offset < end)
+            .map(|offset| self.location(offset))
+            .or_else(|| self.rule(rule_index))
+    }
+
+    fn anywhere(&self, needle: &str) -> Option<RulesLocation> {
+        self.source.find(needle).map(|offset| self.location(offset))
+    }
+
+    fn location(&self, offset: usize) -> RulesLocation {
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        RulesLocation {
+            line,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl LanguageDefinition {
+    /// Checks the definition for problems that would otherwise only show up as
+    /// panics while translating files. `source` is the RON text the definition
+    /// was parsed from and is only used to locate the problems.
+    pub fn validate(&self, source: &str) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let locator = Locator::new(source, &self.ast_rules);
+
+        let mut rule_names: HashMap<&str, usize> = HashMap::new();
+        for (i, rule) in self.ast_rules.iter().enumerate() {
+            if rule_names.contains_key(rule.node_name.as_str()) {
+                report.push(
+                    Severity::Warning,
+                    format!(
+                        "rule `{}` is defined more than once, only the first definition is used",
+                        rule.node_name
+                    ),
+                    locator.rule(i),
+                );
+            } else {
+                rule_names.insert(&rule.node_name, i);
+            }
+        }
+
+        if !rule_names.contains_key("Root") {
+            report.push(
+                Severity::Error,
+                String::from("missing `Root` rule, it is the entry point of the translation"),
+                None,
+            );
+        }
+
+        let mut symbol_kinds: HashSet<&str> = HashSet::new();
+        for symbol_type in &self.symbol_types {
+            if !symbol_kinds.insert(&symbol_type.name) {
+                report.push(
+                    Severity::Warning,
+                    format!(
+                        "symbol type `{}` is defined more than once",
+                        symbol_type.name
+                    ),
+                    locator.anywhere(&format!("\"{}\"", symbol_type.name)),
+                );
+            }
+        }
+
+        for child in &self.global_ast_rules {
+            self.validate_child(child, "global_ast_rules", &rule_names, &mut report, || {
+                locator.anywhere("global_ast_rules")
+            });
+        }
+
+        for (i, rule) in self.ast_rules.iter().enumerate() {
+            for child in &rule.children {
+                self.validate_child(child, &rule.node_name, &rule_names, &mut report, || {
+                    Self::child_location(&locator, i, child)
+                });
+            }
+
+            self.validate_symbol(rule, i, &symbol_kinds, &locator, &mut report);
+        }
+
+        for (name, i) in self.unreachable_rules(&rule_names) {
+            report.push(
+                Severity::Warning,
+                format!("rule `{name}` is never reached from `Root`"),
+                locator.rule(i),
+            );
+        }
+
+        report
+    }
+
+    /// Compiles every `Query` pattern for `language`, so they are only built
+    /// once per definition. Patterns that do not compile are errors.
+    pub(crate) fn compile_queries(
+        &mut self,
+        language: tree_sitter::Language,
+        source: &str,
+    ) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let locator = Locator::new(source, &self.ast_rules);
+
+        let mut patterns: Vec<(&str, &str, Option<RulesLocation>)> = vec![];
+        for child in &self.global_ast_rules {
+            if let TreesitterNodeQuery::Query(pattern) = &child.query {
+                patterns.push((
+                    pattern,
+                    "global_ast_rules",
+                    locator.anywhere("global_ast_rules"),
+                ));
+            }
+        }
+        for (i, rule) in self.ast_rules.iter().enumerate() {
+            for child in &rule.children {
+                if let TreesitterNodeQuery::Query(pattern) = &child.query {
+                    patterns.push((
+                        pattern,
+                        &rule.node_name,
+                        Self::child_location(&locator, i, child),
+                    ));
+                }
+            }
+        }
+
+        let mut queries = HashMap::new();
+        for (pattern, parent, location) in patterns {
+            if queries.contains_key(pattern) {
+                continue;
+            }
+
+            match tree_sitter::Query::new(language, pattern) {
+                Ok(query) => {
+                    if query
+                        .capture_names()
+                        .iter()
+                        .all(|name| name.starts_with('_'))
+                    {
+                        report.push(
+                            Severity::Warning,
+                            format!("query in `{parent}` has no capture, it never produces a node"),
+                            location,
+                        );
+                    }
+                    queries.insert(pattern.to_string(), Arc::new(query));
+                }
+                Err(err) => report.push(
+                    Severity::Error,
+                    format!("invalid query in `{parent}`: {err}"),
+                    location,
+                ),
+            }
+        }
+
+        self.queries = queries;
+        report
+    }
+
+    fn validate_child(
+        &self,
+        child: &Child,
+        parent: &str,
+        rule_names: &HashMap<&str, usize>,
+        report: &mut ValidationReport,
+        location: impl Fn() -> Option<RulesLocation>,
+    ) {
+        if let TreesitterNodeQuery::Path(path) = &child.query {
+            if path.is_empty() {
+                report.push(
+                    Severity::Error,
+                    format!("empty `Path` query in `{parent}`"),
+                    location(),
+                );
+            }
+
+            if path
+                .iter()
+                .any(|query| matches!(query, TreesitterNodeQuery::Path(_)))
+            {
+                report.push(
+                    Severity::Error,
+                    format!("nested `Path` queries are not supported (in `{parent}`)"),
+                    location(),
+                );
+            }
+
+            if path
+                .iter()
+                .any(|query| matches!(query, TreesitterNodeQuery::Query(_)))
+            {
+                report.push(
+                    Severity::Error,
+                    format!("`Query` cannot be used inside a `Path` (in `{parent}`)"),
+                    location(),
+                );
+            }
+        }
+
+        if let DirectOrRule::Rule(name) = &child.rule {
+            if !rule_names.contains_key(name.as_str()) {
+                report.push(
+                    Severity::Error,
+                    format!("`{parent}` references unknown rule `{name}`"),
+                    location(),
+                );
+            }
+        }
+    }
+
+    fn validate_symbol(
+        &self,
+        rule: &Rule,
+        rule_index: usize,
+        symbol_kinds: &HashSet<&str>,
+        locator: &Locator,
+        report: &mut ValidationReport,
+    ) {
+        let produced = |name: &str| {
+            rule.children
+                .iter()
+                .chain(&self.global_ast_rules)
+                .any(|child| match &child.rule {
+                    DirectOrRule::Direct(kind) | DirectOrRule::Rule(kind) => kind == name,
+                })
+        };
+
+        let mut check_node = |key: &str, name: &str| {
+            if !produced(name) {
+                report.push(
+                    Severity::Error,
+                    format!(
+                        "`{key}` of rule `{}` is `{name}`, but no child produces that node",
+                        rule.node_name
+                    ),
+                    locator.key_value_in_rule(rule_index, key, name),
+                );
+            }
+        };
+
+        match &rule.symbol {
+            Symbol::Init {
+                kind,
+                name_node,
+                type_node,
+            } => {
+                check_node("name_node", name_node);
+                if let Some(type_node) = type_node {
+                    check_node("type_node", type_node);
+                }
+
+                if !symbol_kinds.contains(kind.as_str()) {
+                    report.push(
+                        Severity::Error,
+                        format!(
+                            "symbol type `{kind}` of rule `{}` is missing from `symbol_types`",
+                            rule.node_name
+                        ),
+                        locator.key_value_in_rule(rule_index, "type", kind),
+                    );
+                }
+            }
+            Symbol::Field { name_node } => check_node("name_node", name_node),
+            _ => {}
+        }
+
+        if rule.allow_overloading && !matches!(rule.symbol, Symbol::Init { .. }) {
+            report.push(
+                Severity::Warning,
+                format!(
+                    "`allow_overloading` of rule `{}` has no effect without an `Init` symbol",
+                    rule.node_name
+                ),
+                locator.in_rule(rule_index, "allow_overloading"),
+            );
+        }
+    }
+
+    fn child_location(
+        locator: &Locator,
+        rule_index: usize,
+        child: &Child,
+    ) -> Option<RulesLocation> {
+        match &child.rule {
+            DirectOrRule::Rule(name) => locator.in_rule(rule_index, &format!("Rule(\"{name}\")")),
+            DirectOrRule::Direct(name) => {
+                locator.in_rule(rule_index, &format!("Direct(\"{name}\")"))
+            }
+        }
+    }
+
+    fn unreachable_rules<'a>(
+        &'a self,
+        rule_names: &HashMap<&'a str, usize>,
+    ) -> Vec<(&'a str, usize)> {
+        let mut reached: HashSet<&str> = HashSet::new();
+        let mut to_visit: Vec<&str> = vec!["Root"];
+
+        for child in &self.global_ast_rules {
+            if let DirectOrRule::Rule(name) = &child.rule {
+                to_visit.push(name);
+            }
+        }
+
+        while let Some(name) = to_visit.pop() {
+            if !reached.insert(name) {
+                continue;
+            }
+
+            if let Some(rule) = rule_names.get(name).map(|i| &self.ast_rules[*i]) {
+                for child in &rule.children {
+                    if let DirectOrRule::Rule(name) = &child.rule {
+                        to_visit.push(name);
+                    }
+                }
+            }
+        }
+
+        if !reached.contains("Root") || !rule_names.contains_key("Root") {
+            // Everything would be reported when the entry point is missing
+            return vec![];
+        }
+
+        let mut unreachable: Vec<(&str, usize)> = rule_names
+            .iter()
+            .filter(|(name, _)| !reached.contains(*name))
+            .map(|(name, i)| (*name, *i))
+            .collect();
+        unreachable.sort_by_key(|(_, i)| *i);
+
+        unreachable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LanguageDefinition, Severity};
+
+    const HEADER: &str = r#"(
+    language: (
+        name: "Test",
+        file_extensions: ["test"],
+        library_paths: (env_variables: [], linux: [], windows: [], macos: []),
+    ),
+    keywords: [],
+    symbol_types: [
+        (name: "Message", completion_type: Class, highlight_type: Class),
+    ],
+    global_ast_rules: [],
+"#;
+
+    fn validate(ast_rules: &str) -> Vec<(Severity, String, Option<usize>)> {
+        let source = format!("{HEADER}    ast_rules: [\n{ast_rules}\n    ],\n)");
+        let language_def = LanguageDefinition::parse(&source).unwrap();
+
+        language_def
+            .validate(&source)
+            .diagnostics
+            .into_iter()
+            .map(|diag| (diag.severity, diag.message, diag.location.map(|l| l.line)))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_rules() {
+        let diags = validate(
+            r#"        Rule(
+            node_name: "Root",
+            children: [(query: Kind("message"), rule: Rule("Message"))],
+        ),
+        Rule(
+            node_name: "Message",
+            is_scope: true,
+            symbol: Init(type: "Message", name_node: "Name"),
+            children: [(query: Kind("message_name"), rule: Direct("Name"))],
+        ),"#,
+        );
+
+        assert!(diags.is_empty(), "{diags:?}");
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let diags = validate(
+            r#"        Rule(
+            node_name: "Message",
+            symbol: Init(type: "Unknown", name_node: "Name"),
+            children: [
+                (query: Kind("message_body"), rule: Rule("MessageBody")),
+                (query: Path([]), rule: Direct("Body")),
+            ],
+        ),"#,
+        );
+
+        let errors: Vec<&(Severity, String, Option<usize>)> = diags
+            .iter()
+            .filter(|(severity, _, _)| *severity == Severity::Error)
+            .collect();
+
+        assert_eq!(errors.len(), 5, "{diags:?}");
+        assert!(errors[0].1.contains("`Root`"));
+        assert!(errors[1].1.contains("unknown rule `MessageBody`"));
+        assert_eq!(errors[1].2, Some(17));
+        assert!(errors[2].1.contains("empty `Path`"));
+        assert!(errors[3].1.contains("`name_node`"));
+        assert_eq!(errors[3].2, Some(15));
+        assert!(errors[4].1.contains("`Unknown`"));
+    }
+
+    #[test]
+    fn test_query_in_path() {
+        let diags = validate(
+            r#"        Rule(
+            node_name: "Root",
+            children: [(query: Path([Kind("message"), Query("(message_name) @name")]), rule: Direct("Name"))],
+        ),"#,
+        );
+
+        assert_eq!(
+            diags,
+            vec![(
+                Severity::Error,
+                String::from("`Query` cannot be used inside a `Path` (in `Root`)"),
+                Some(15)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_rule() {
+        let diags = validate(
+            r#"        Rule(node_name: "Root"),
+        Rule(node_name: "Orphan"),"#,
+        );
+
+        assert_eq!(
+            diags,
+            vec![(
+                Severity::Warning,
+                String::from("rule `Orphan` is never reached from `Root`"),
+                Some(14)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_syntax_error_location() {
+        let report =
+            LanguageDefinition::parse("(\n    language: (\n        name: 12,\n").unwrap_err();
+
+        assert!(report.has_errors());
+        assert_eq!(report.diagnostics[0].location.unwrap().line, 3);
+    }
+}
@@ -1,9 +1,15 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::sync::RwLock;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
-use crate::language_def::{self, LanguageDefinition};
-use crate::plugin_manager::{self, OnState, PluginManager, PluginsResult};
+use crate::indexer::WorkspaceIndexer;
+use crate::language_def::LanguageRegistry;
+use crate::plugin_manager::{self, Buffer, BufferSource, OnState, PluginManager};
 use crate::project::workspace::{FileManagement, LanguageActions, Workspace};
+use crate::rules_watcher::RulesWatcher;
+use tokio::task::JoinHandle;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
@@ -16,19 +22,228 @@ use std::fs::File;
 
 pub struct Backend {
     client: Client,
-    workspace: RwLock<Workspace>,
-    plugin_manager: RwLock<PluginManager>,
+    workspace: Arc<RwLock<Workspace>>,
+    plugin_manager: Arc<PluginManager>,
+    registry: Arc<LanguageRegistry>,
+    // Whether the client accepts file watchers registered by the server
+    watch_files: AtomicBool,
+    // Whether the client accepts progress created by the server
+    report_progress: AtomicBool,
+    // Whether the client answers `workspace/configuration` requests
+    fetch_configuration: AtomicBool,
+    // Plugin runs on `Change`, cancelled by the next edit of the file and
+    // removed once they finished
+    change_runs: Arc<Mutex<HashMap<Url, JoinHandle<()>>>>,
+    // Plugins whose last run failed, only shown again once they succeeded
+    failed_plugins: Arc<Mutex<HashSet<String>>>,
 }
 
 impl Backend {
-    pub fn init(client: Client, ts_language: tree_sitter::Language) -> Backend {
+    pub fn init(client: Client, registry: Arc<LanguageRegistry>) -> Backend {
         Backend {
             client,
-            workspace: Workspace::new(ts_language).into(),
-            plugin_manager: PluginManager::new().into(),
+            workspace: Arc::new(Workspace::new(registry.clone()).into()),
+            plugin_manager: Arc::new(PluginManager::new()),
+            registry,
+            watch_files: AtomicBool::new(false),
+            report_progress: AtomicBool::new(false),
+            fetch_configuration: AtomicBool::new(false),
+            change_runs: Arc::new(Mutex::new(HashMap::new())),
+            failed_plugins: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
+    /// Asks the client for the settings of each folder, under the name of the
+    /// server.
+    async fn fetch_root_settings(&self, roots: &[PathBuf]) {
+        if roots.is_empty() || !self.fetch_configuration.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let items = roots
+            .iter()
+            .map(|root| ConfigurationItem {
+                scope_uri: Url::from_directory_path(root).ok(),
+                section: Some(self.registry.get_name()),
+            })
+            .collect();
+
+        match self.client.configuration(items).await {
+            Ok(settings) => {
+                let mut workspace = self.workspace.write().unwrap();
+                for (root, settings) in roots.iter().zip(settings) {
+                    workspace.update_root_settings(root, settings);
+                }
+            }
+            Err(error) => warn!("Settings of the workspace folders unavailable: {error}"),
+        }
+    }
+
+    fn index_roots(&self, roots: Vec<PathBuf>) {
+        WorkspaceIndexer::spawn(
+            roots,
+            self.registry.clone(),
+            self.client.clone(),
+            self.workspace.clone(),
+            self.report_progress.load(Ordering::Relaxed),
+        );
+    }
+
+    /// Files of the registered languages, in the workspace and in their
+    /// library directories.
+    fn get_file_watchers(&self) -> Vec<FileSystemWatcher> {
+        let mut watchers = vec![];
+
+        for language in self.registry.languages() {
+            let language = language.definition().language.clone();
+            let extensions: Vec<&str> = language
+                .file_extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.'))
+                .collect();
+            let pattern = match extensions.as_slice() {
+                [] => continue,
+                [extension] => format!("**/*.{extension}"),
+                extensions => format!("**/*.{{{}}}", extensions.join(",")),
+            };
+
+            watchers.push(FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.clone()),
+                kind: None,
+            });
+            for directory in language.library_paths.directories() {
+                let Ok(base_uri) = Url::from_directory_path(directory) else {
+                    continue;
+                };
+
+                watchers.push(FileSystemWatcher {
+                    glob_pattern: GlobPattern::Relative(RelativePattern {
+                        base_uri: OneOf::Right(base_uri),
+                        pattern: pattern.clone(),
+                    }),
+                    kind: None,
+                });
+            }
+        }
+
+        watchers
+    }
+
+    /// Runs the plugins on a task, their diagnostics are published with the
+    /// ones of the file once they all finished. Plugins get the buffers as
+    /// they are once their debounce is over. No task is spawned when no
+    /// plugin is declared for `state`.
+    fn run_plugins(&self, uri: Url, state: OnState) -> Option<JoinHandle<()>> {
+        if !self.plugin_manager.has_plugins(&state) {
+            return None;
+        }
+
+        self.workspace.read().unwrap().get_file(&uri)?;
+        let buffers: BufferSource = {
+            let workspace = self.workspace.clone();
+            let uri = uri.clone();
+            Arc::new(move |with_dirty_buffers| {
+                let to_buffer = |file: &crate::project::File| {
+                    Some(Buffer {
+                        file: file.uri.to_file_path().ok()?.display().to_string(),
+                        version: file.version,
+                        source_code: file.source_code.clone(),
+                    })
+                };
+
+                let workspace = workspace.read().unwrap();
+                let buffer = to_buffer(workspace.get_file(&uri)?)?;
+                let dirty_buffers = if with_dirty_buffers {
+                    workspace
+                        .get_dirty_files()
+                        .into_iter()
+                        .filter(|file| file.uri != uri)
+                        .filter_map(to_buffer)
+                        .collect()
+                } else {
+                    vec![]
+                };
+
+                Some((buffer, dirty_buffers))
+            })
+        };
+
+        let client = self.client.clone();
+        let workspace = self.workspace.clone();
+        let plugin_manager = self.plugin_manager.clone();
+        let failed_plugins = self.failed_plugins.clone();
+        let change_runs = self.change_runs.clone();
+
+        Some(tokio::spawn(async move {
+            let result = plugin_manager.run_plugins(&uri, buffers, &state).await;
+            let is_change = state == OnState::Change;
+            if is_change {
+                // Unless a newer edit replaced it already
+                let mut change_runs = change_runs.lock().unwrap();
+                if change_runs
+                    .get(&uri)
+                    .is_some_and(|run| run.id() == tokio::task::id())
+                {
+                    change_runs.remove(&uri);
+                }
+            }
+
+            // A failure is shown once and then only logged until the plugin
+            // succeeds again, its diagnostic stays until then
+            for status in result.status {
+                let diagnostics = match status.error {
+                    Some(error) => {
+                        let message = format!("Plugin {} failed: {error}", status.name);
+                        warn!("{message}");
+                        client.log_message(MessageType::ERROR, &message).await;
+                        if failed_plugins.lock().unwrap().insert(status.name) {
+                            client.show_message(MessageType::ERROR, &message).await;
+                        }
+
+                        vec![Diagnostic {
+                            severity: Some(DiagnosticSeverity::ERROR),
+                            source: Some(String::from("plugins")),
+                            message,
+                            ..Default::default()
+                        }]
+                    }
+                    None => {
+                        failed_plugins.lock().unwrap().remove(&status.name);
+                        vec![]
+                    }
+                };
+                if let Some(plugin_uri) = status.uri {
+                    client
+                        .publish_diagnostics(plugin_uri, diagnostics, None)
+                        .await;
+                }
+            }
+
+            for plugin_notification in result.notification {
+                client
+                    .send_notification::<plugin_manager::CustomNotification>(plugin_notification)
+                    .await;
+            }
+
+            if !result.diagnostic.is_empty() {
+                let Some(mut diagnostics) = ({
+                    let workspace = workspace.read().unwrap();
+                    workspace.get_file(&uri).map(|_| {
+                        if is_change {
+                            workspace.get_quick_diagnostics(&uri)
+                        } else {
+                            workspace.get_full_diagnostics(&uri)
+                        }
+                    })
+                }) else {
+                    return;
+                };
+                diagnostics.extend(result.diagnostic);
+                client.publish_diagnostics(uri, diagnostics, None).await;
+            }
+        }))
+    }
+
     pub fn publish_diagnostics(&self, uri: Url, diags: Vec<Diagnostic>) {
         let client = self.client.clone();
         tokio::spawn(async move { client.publish_diagnostics(uri, diags, None).await });
@@ -38,10 +253,7 @@ impl Backend {
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
-        let log_file_path = env::temp_dir().join(format!(
-            "lever-{}.log",
-            LanguageDefinition::get().language.name.to_lowercase()
-        ));
+        let log_file_path = env::temp_dir().join(format!("lever-{}.log", self.registry.get_name()));
 
         if let Ok(log_file) = File::create(log_file_path) {
             let result = WriteLogger::init(LevelFilter::Debug, Config::default(), log_file);
@@ -57,12 +269,49 @@ impl LanguageServer for Backend {
             error!("{info}");
         }));
 
-        if let Some(root_uri) = params.root_uri.clone() {
-            self.workspace
-                .write()
-                .unwrap()
-                .set_root_path(root_uri.to_file_path().ok());
-        }
+        self.watch_files.store(
+            params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.did_change_watched_files)
+                .and_then(|capabilities| capabilities.dynamic_registration)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+
+        self.fetch_configuration.store(
+            params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.configuration)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+
+        self.report_progress.store(
+            params
+                .capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.work_done_progress)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+
+        let roots = match &params.workspace_folders {
+            Some(folders) => folders
+                .iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .collect(),
+            None => params
+                .root_uri
+                .iter()
+                .filter_map(|root_uri| root_uri.to_file_path().ok())
+                .collect(),
+        };
+        self.workspace.write().unwrap().set_roots(roots);
 
         info!(
             "Inititalizing Language Server with options: {:?}",
@@ -70,8 +319,6 @@ impl LanguageServer for Backend {
         );
         if let Some(options) = params.initialization_options {
             self.plugin_manager
-                .write()
-                .unwrap()
                 .load_plugins(params.root_uri, options.to_string().as_str());
         }
 
@@ -81,7 +328,7 @@ impl LanguageServer for Backend {
                     SemanticTokensServerCapabilities::SemanticTokensOptions(
                         SemanticTokensOptions {
                             range: Some(false),
-                            legend: language_def::LanguageDefinition::get_semantic_token_legend(),
+                            legend: self.registry.get_semantic_token_legend(),
                             full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
                             ..Default::default()
                         },
@@ -101,7 +348,13 @@ impl LanguageServer for Backend {
                         save: Some(TextDocumentSyncSaveOptions::Supported(true)),
                     },
                 )),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
                 definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Right(RenameOptions {
                     prepare_provider: Some(false),
                     work_done_progress_options: WorkDoneProgressOptions::default(),
@@ -121,9 +374,43 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         info!("Lsp initialized");
+
+        if self.watch_files.load(Ordering::Relaxed) {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: self.get_file_watchers(),
+            };
+            let registration = Registration {
+                id: String::from("lever-file-watchers"),
+                method: String::from("workspace/didChangeWatchedFiles"),
+                register_options: serde_json::to_value(options).ok(),
+            };
+
+            if let Err(error) = self.client.register_capability(vec![registration]).await {
+                warn!("File watchers could not be registered: {error}");
+            }
+        }
+
+        // Library paths of the settings are needed to link the indexed files
+        let roots = self.workspace.read().unwrap().get_roots();
+        self.fetch_root_settings(&roots).await;
+        self.index_roots(roots);
+
+        for (index, language) in self.registry.languages().iter().enumerate() {
+            if let Some(rules_path) = &language.rules_path {
+                RulesWatcher::spawn(
+                    rules_path.clone(),
+                    index,
+                    self.registry.clone(),
+                    self.client.clone(),
+                    self.workspace.clone(),
+                );
+            }
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
+        self.plugin_manager.shutdown().await;
+
         info!("Lsp stopped");
         Ok(())
     }
@@ -132,57 +419,153 @@ impl LanguageServer for Backend {
         let doc = params.text_document;
         info!("Opening file: {}", doc.uri);
 
-        let mut diagnostics = {
+        let (diagnostics, dependent_diagnostics) = {
+            let mut workspace = self.workspace.write().unwrap();
+            let dependents = workspace.open_file(doc.uri.clone(), &doc.text, doc.version);
+
+            (
+                workspace.get_full_diagnostics(&doc.uri),
+                dependents
+                    .into_iter()
+                    .map(|url| {
+                        let diagnostics = workspace.get_quick_diagnostics(&url);
+                        (url, diagnostics)
+                    })
+                    .collect::<Vec<(Url, Vec<Diagnostic>)>>(),
+            )
+        };
+        for (url, diagnostics) in dependent_diagnostics {
+            self.publish_diagnostics(url, diagnostics);
+        }
+
+        self.publish_diagnostics(doc.uri.clone(), diagnostics);
+        self.run_plugins(doc.uri, OnState::Open);
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let (diagnostics, dependent_diagnostics) = {
             let mut workspace = self.workspace.write().unwrap();
-            workspace.add_file(doc.uri.clone(), &doc.text);
+            let dependents = workspace.update_file(
+                &params.text_document.uri,
+                Some(params.text_document.version),
+                params.content_changes,
+            );
+
+            (
+                workspace.get_quick_diagnostics(&params.text_document.uri),
+                dependents
+                    .into_iter()
+                    .map(|url| {
+                        let diagnostics = workspace.get_quick_diagnostics(&url);
+                        (url, diagnostics)
+                    })
+                    .collect::<Vec<(Url, Vec<Diagnostic>)>>(),
+            )
+        };
 
-            workspace.get_full_diagnostics(&doc.uri)
+        self.publish_diagnostics(params.text_document.uri.clone(), diagnostics);
+        for (url, diagnostics) in dependent_diagnostics {
+            self.publish_diagnostics(url, diagnostics);
+        }
+
+        let uri = params.text_document.uri;
+        // Locked while the run starts, so that it cannot finish and remove
+        // itself before it is inserted
+        let previous_run = {
+            let mut change_runs = self.change_runs.lock().unwrap();
+            match self.run_plugins(uri.clone(), OnState::Change) {
+                Some(run) => change_runs.insert(uri, run),
+                None => change_runs.remove(&uri),
+            }
         };
+        if let Some(previous_run) = previous_run {
+            previous_run.abort();
+        }
+    }
 
-        let mut plugin_result: PluginsResult = self
-            .plugin_manager
-            .write()
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        info!("Closing file: {}", params.text_document.uri);
+        if let Some(run) = self
+            .change_runs
+            .lock()
             .unwrap()
-            .run_plugins(&doc.uri, &OnState::Save);
+            .remove(&params.text_document.uri)
+        {
+            run.abort();
+        }
 
-        diagnostics.append(&mut plugin_result.diagnostic);
+        let (removed, updated) = {
+            let mut workspace = self.workspace.write().unwrap();
+            let (removed, updated) = workspace.close_file(&params.text_document.uri);
+
+            (
+                removed,
+                updated
+                    .into_iter()
+                    .map(|url| {
+                        let diagnostics = workspace.get_full_diagnostics(&url);
+                        (url, diagnostics)
+                    })
+                    .collect::<Vec<(Url, Vec<Diagnostic>)>>(),
+            )
+        };
 
-        self.publish_diagnostics(doc.uri, diagnostics);
+        for url in removed {
+            self.publish_diagnostics(url, vec![]);
+        }
+        for (url, diagnostics) in updated {
+            self.publish_diagnostics(url, diagnostics);
+        }
     }
 
-    async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let diagnostics = {
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let (removed, updated) = {
             let mut workspace = self.workspace.write().unwrap();
-            workspace.update_file(&params.text_document.uri, params.content_changes);
+            let mut removed: Vec<Url> = vec![];
+            let mut updated: Vec<Url> = vec![];
+
+            for change in params.changes {
+                info!("File changed on disk: {} ({:?})", change.uri, change.typ);
+                let (mut change_removed, mut change_updated) =
+                    workspace.apply_disk_change(&change.uri, change.typ);
+                removed.append(&mut change_removed);
+                updated.append(&mut change_updated);
+            }
 
-            workspace.get_quick_diagnostics(&params.text_document.uri)
+            updated.sort();
+            updated.dedup();
+            updated.retain(|url| !removed.contains(url));
+
+            (
+                removed,
+                updated
+                    .into_iter()
+                    .map(|url| {
+                        let diagnostics = workspace.get_full_diagnostics(&url);
+                        (url, diagnostics)
+                    })
+                    .collect::<Vec<(Url, Vec<Diagnostic>)>>(),
+            )
         };
 
-        self.publish_diagnostics(params.text_document.uri, diagnostics);
+        for url in removed {
+            self.publish_diagnostics(url, vec![]);
+        }
+        for (url, diagnostics) in updated {
+            self.publish_diagnostics(url, diagnostics);
+        }
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        let mut diagnostics = {
-            let workspace = self.workspace.read().unwrap();
+        let diagnostics = {
+            let mut workspace = self.workspace.write().unwrap();
+            workspace.mark_saved(&params.text_document.uri);
 
             workspace.get_full_diagnostics(&params.text_document.uri)
         };
 
-        let mut plugin_result: PluginsResult = self
-            .plugin_manager
-            .write()
-            .unwrap()
-            .run_plugins(&params.text_document.uri, &OnState::Save);
-
-        diagnostics.append(&mut plugin_result.diagnostic);
-
-        for plugin_notification in plugin_result.notification {
-            self.client
-                .send_notification::<plugin_manager::CustomNotification>(plugin_notification)
-                .await;
-        }
-
-        self.publish_diagnostics(params.text_document.uri, diagnostics);
+        self.publish_diagnostics(params.text_document.uri.clone(), diagnostics);
+        self.run_plugins(params.text_document.uri, OnState::Save);
     }
 
     async fn goto_definition(
@@ -202,6 +585,46 @@ impl LanguageServer for Backend {
         })
     }
 
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let response = {
+            let workspace = self.workspace.read().unwrap();
+
+            Ok(workspace.get_references(
+                &params.text_document_position.text_document.uri,
+                params.text_document_position.position,
+                params.context.include_declaration,
+            ))
+        };
+
+        response
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let maybe_symbols = {
+            let workspace = self.workspace.read().unwrap();
+
+            workspace.get_document_symbols(&params.text_document.uri)
+        };
+
+        Ok(maybe_symbols.map(DocumentSymbolResponse::Nested))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let symbols = {
+            let workspace = self.workspace.read().unwrap();
+
+            workspace.get_workspace_symbols(&params.query)
+        };
+
+        Ok(Some(symbols))
+    }
+
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
         let maybe_hover_info = {
             let workspace = self.workspace.read().unwrap();
@@ -220,6 +643,26 @@ impl LanguageServer for Backend {
         })
     }
 
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let code_lenses = {
+            let workspace = self.workspace.read().unwrap();
+
+            workspace.get_code_lenses(&params.text_document.uri)
+        };
+
+        Ok(Some(code_lenses))
+    }
+
+    async fn code_lens_resolve(&self, params: CodeLens) -> Result<CodeLens> {
+        let code_lens = {
+            let workspace = self.workspace.read().unwrap();
+
+            workspace.resolve_code_lens(params)
+        };
+
+        Ok(code_lens)
+    }
+
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
@@ -264,7 +707,43 @@ impl LanguageServer for Backend {
     }
 
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
-        let mut workspace = self.workspace.write().unwrap();
-        workspace.update_settings(params.settings);
+        let roots = {
+            let mut workspace = self.workspace.write().unwrap();
+            workspace.update_settings(params.settings);
+            workspace.get_roots()
+        };
+        self.fetch_root_settings(&roots).await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let to_path = |folder: WorkspaceFolder| folder.uri.to_file_path().ok();
+        let removed_roots: Vec<PathBuf> = params
+            .event
+            .removed
+            .into_iter()
+            .filter_map(to_path)
+            .collect();
+        let added_roots: Vec<PathBuf> =
+            params.event.added.into_iter().filter_map(to_path).collect();
+        info!("Workspace folders added: {added_roots:?}, removed: {removed_roots:?}");
+
+        let removed_files = {
+            let mut workspace = self.workspace.write().unwrap();
+            let removed_files: Vec<Url> = removed_roots
+                .iter()
+                .flat_map(|root| workspace.remove_root(root))
+                .collect();
+            for root in &added_roots {
+                workspace.add_root(root.clone());
+            }
+
+            removed_files
+        };
+        for url in removed_files {
+            self.publish_diagnostics(url, vec![]);
+        }
+
+        self.fetch_root_settings(&added_roots).await;
+        self.index_roots(added_roots);
     }
 }
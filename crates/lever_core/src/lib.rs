@@ -1,10 +1,12 @@
 #![warn(clippy::all)]
 #![allow(clippy::cast_possible_truncation, clippy::wildcard_imports)]
+mod indexer;
 mod language_def;
 mod language_server;
 mod lsp_mappings;
 mod plugin_manager;
 mod project;
+mod rules_watcher;
 mod settings;
 mod setup;
 mod utils;
@@ -14,17 +16,52 @@ extern crate log;
 
 pub use language_def::*;
 pub use lsp_mappings::*;
-pub use project::{Ast, Node, NodeKind, Translator};
+pub use project::{
+    Ast, Node, NodeKind, RulesTranslator, StableSymbolId, SymbolPath, Translator, VisitNode,
+    Visitable,
+};
 pub use setup::*;
 
+/// Files are only exposed to the benchmarks.
+#[cfg(feature = "internals")]
+#[doc(hidden)]
+pub use project::File;
+
+/// Used by the code generated with `rules_translator!`.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::project::ReusableNodes;
+}
+
 pub async fn start_server(setup: &Setup) {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    language_def::LanguageDefinition::load(&setup.language_def);
+    let mut registry = language_def::LanguageRegistry::default();
+    for (i, language) in setup.languages.iter().enumerate() {
+        let name = language.rules_path.as_ref().map_or_else(
+            || format!("language #{}", i + 1),
+            |path| path.display().to_string(),
+        );
+
+        match registry.register(language) {
+            Ok(report) => {
+                if !report.is_empty() {
+                    eprintln!("Rules of {name} loaded with warnings:\n{report}");
+                }
+            }
+            Err(report) => {
+                eprintln!(
+                    "Failed to load rules of {name}, the language server will not start:\n{report}"
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    let registry = std::sync::Arc::new(registry);
 
     let (service, socket) = tower_lsp::LspService::new(|client| {
-        language_server::Backend::init(client, setup.treesitter_language)
+        language_server::Backend::init(client, registry.clone())
     });
     tower_lsp::Server::new(stdin, stdout, socket)
         .serve(service)
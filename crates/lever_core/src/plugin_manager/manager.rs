@@ -1,13 +1,29 @@
 use crate::plugin_manager::notification::CustomParams;
+use crate::plugin_manager::process::PluginProcess;
 use serde::{Deserialize, Serialize};
-use serde_json::{from_str, to_string};
-use std::io::Write;
-use std::process::{Command, Stdio};
+use serde_json::{from_str, json, to_string, Value};
+use std::collections::HashMap;
+use std::io;
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio::task::{AbortHandle, JoinSet};
 use tower_lsp::lsp_types::Diagnostic;
 use tower_lsp::lsp_types::*;
 
+// Delay before restarting a crashed persistent plugin, doubled on each crash
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Runs the plugins of the configuration. Each plugin runs on its own task,
+/// a failing plugin is reported in the result and never stops the others.
 pub struct PluginManager {
-    plugins: Vec<Plugin>,
+    plugins: RwLock<Vec<Arc<PluginRunner>>>,
+    // Runs and starts of persistent plugins in flight, aborted on shutdown
+    tasks: std::sync::Mutex<Vec<AbortHandle>>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -31,28 +47,102 @@ pub struct Plugin {
     on: Vec<OnState>,
     arguments: Vec<Argument>,
     state: bool,
+    /// Started once and kept alive, it is sent JSON-RPC requests instead of
+    /// being run on each event
+    #[serde(default)]
+    persistent: bool,
+    /// Time a run may take before the plugin is stopped, in milliseconds
+    #[serde(default = "default_timeout")]
+    timeout_ms: u64,
+    /// Time without edits before a run on `Change`, in milliseconds
+    #[serde(default = "default_debounce")]
+    debounce_ms: u64,
+    /// Format of the input of a run
+    #[serde(default)]
+    input: InputFormat,
+    /// Also sent the open buffers with unsaved changes, with the structured
+    /// input only
+    #[serde(default)]
+    dirty_buffers: bool,
+}
+
+/// Input of the plugins, `Arguments` is the array of arguments the first
+/// plugins were written for and stays the default.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    #[default]
+    Arguments,
+    Structured,
+}
+
+fn default_timeout() -> u64 {
+    10_000
 }
+
+fn default_debounce() -> u64 {
+    300
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Argument {
     key: String,
     value: String,
 }
 
+/// Content of an editor buffer, it may differ from the file on disk.
+#[derive(Serialize, Clone)]
+pub struct Buffer {
+    pub file: String,
+    /// `None` when the content was read from disk
+    pub version: Option<i32>,
+    pub source_code: String,
+}
+
+/// Gives the buffer of the file a plugin runs on, with the other open
+/// buffers that have unsaved changes when asked for them. It is called once
+/// the run is no longer debounced, `None` when the file was closed.
+pub type BufferSource = Arc<dyn Fn(bool) -> Option<(Buffer, Vec<Buffer>)> + Send + Sync>;
+
+/// Input of a run, written to the standard input of the plugins that are not
+/// persistent and sent as the params of `fileChanged` to the others. Plugins
+/// with the `Arguments` input are only written the arguments.
+#[derive(Serialize)]
+struct PluginInput {
+    arguments: Vec<Argument>,
+    /// Only sent with the structured input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    buffer: Option<Buffer>,
+    /// Only sent to the plugins asking for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dirty_buffers: Option<Vec<Buffer>>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CustomResult {
     output_type: TypesNotification,
     data: String,
 }
 
+/// Outcome of a plugin run, `error` is `None` when it succeeded.
+pub struct PluginStatus {
+    pub name: String,
+    /// The executable of the plugin, failures are reported on it
+    pub uri: Option<Url>,
+    pub error: Option<String>,
+}
+
 pub struct PluginsResult {
     pub diagnostic: Vec<Diagnostic>,
     pub notification: Vec<CustomParams>,
+    pub status: Vec<PluginStatus>,
 }
 impl PluginsResult {
     pub fn new() -> PluginsResult {
         PluginsResult {
             diagnostic: Vec::new(),
             notification: Vec::new(),
+            status: Vec::new(),
         }
     }
 }
@@ -60,82 +150,527 @@ impl PluginsResult {
 impl PluginManager {
     pub fn new() -> PluginManager {
         PluginManager {
-            plugins: Vec::new(),
-        }
-    }
-    pub fn load_plugins(&mut self, uri: Option<Url>, json_str: &str) {
-        if let Ok(mut plugins) = from_str::<Vec<Plugin>>(json_str) {
-            if let Some(url) = uri {
-                let key = String::from("workspace");
-                for plugin in &mut plugins {
-                    plugin.arguments.push(Argument {
-                        key: key.clone(),
-                        value: url
-                            .to_file_path()
-                            .unwrap()
-                            .into_os_string()
-                            .into_string()
-                            .unwrap(),
-                    });
-                }
+            plugins: RwLock::new(Vec::new()),
+            tasks: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn load_plugins(&self, uri: Option<Url>, json_str: &str) {
+        let mut plugins = match from_str::<Vec<Plugin>>(json_str) {
+            Ok(plugins) => plugins,
+            Err(error) => {
+                warn!("Invalid plugin configuration: {error}");
+                return;
+            }
+        };
+
+        if let Some(path) = uri.and_then(|url| url.to_file_path().ok()) {
+            let key = String::from("workspace");
+            for plugin in &mut plugins {
+                plugin.arguments.push(Argument {
+                    key: key.clone(),
+                    value: path.display().to_string(),
+                });
             }
+        }
+
+        let runners: Vec<Arc<PluginRunner>> = plugins
+            .into_iter()
+            .map(|plugin| {
+                Arc::new(PluginRunner {
+                    plugin,
+                    persistent: Mutex::new(PersistentPlugin::new()),
+                })
+            })
+            .collect();
+
+        // Paid once at startup rather than on the first event
+        for runner in runners.iter().filter(|runner| runner.plugin.persistent) {
+            let runner = runner.clone();
+            let start = tokio::spawn(async move {
+                runner.persistent.lock().await.process(&runner.plugin).await;
+            });
+            self.track(start.abort_handle());
+        }
+
+        *self.plugins.write().unwrap() = runners;
+    }
 
-            self.plugins = plugins;
+    /// Aborts the runs in flight and stops the persistent plugins.
+    pub async fn shutdown(&self) {
+        let runners = std::mem::take(&mut *self.plugins.write().unwrap());
+        for task in std::mem::take(&mut *self.tasks.lock().unwrap()) {
+            task.abort();
         }
+        for runner in runners {
+            let mut persistent = runner.persistent.lock().await;
+            persistent.stopped = true;
+            if let Some(process) = persistent.process.take() {
+                process.shutdown().await;
+            }
+        }
+    }
+
+    /// Whether a plugin is declared for `state`.
+    pub fn has_plugins(&self, state: &OnState) -> bool {
+        self.plugins
+            .read()
+            .unwrap()
+            .iter()
+            .any(|runner| runner.plugin.on.contains(state))
     }
 
-    pub fn run_plugins(&mut self, file: &Url, state: &OnState) -> PluginsResult {
+    /// Runs the plugins declared for `state` on the buffer of `file`.
+    /// Dropping the returned future cancels the runs, which stops the
+    /// plugins that are not persistent.
+    pub async fn run_plugins(
+        &self,
+        file: &Url,
+        buffers: BufferSource,
+        state: &OnState,
+    ) -> PluginsResult {
         let mut plugins_result: PluginsResult = PluginsResult::new();
-        for plugin in &mut self.plugins.clone() {
-            let key = String::from("file");
-            plugin.arguments.push(Argument {
-                key: key.clone(),
-                value: file
-                    .to_file_path()
-                    .unwrap()
-                    .into_os_string()
-                    .into_string()
-                    .unwrap(),
-            });
-            if plugin.on.contains(state) {
-                let json_str = PluginManager::execute(plugin.clone());
-                let results: CustomResult = from_str(json_str.as_str()).unwrap();
-
-                match results.output_type {
-                    TypesNotification::Diagnostic => {
-                        let mut diag: Vec<Diagnostic> = from_str(results.data.as_str()).unwrap();
-                        plugins_result.diagnostic.append(&mut diag);
-                    }
-                    TypesNotification::Notification => {
-                        let notification: CustomParams = from_str(results.data.as_str()).unwrap();
-                        plugins_result.notification.push(notification);
-                    }
-                    TypesNotification::Nothing => {}
-                }
+
+        let runners = self.plugins.read().unwrap().clone();
+        let mut runs = JoinSet::new();
+        // A run that panicked only has its task id left
+        let mut plugins = HashMap::new();
+        for runner in runners {
+            if runner.plugin.on.contains(state) {
+                let plugin = (runner.plugin.name.clone(), runner.plugin.path.clone());
+                let file = file.clone();
+                let buffers = buffers.clone();
+                let state = state.clone();
+                let run = runs.spawn(async move { runner.run(file, buffers, state).await });
+                plugins.insert(run.id(), plugin);
+                self.track(run);
             }
         }
+
+        while let Some(run) = runs.join_next_with_id().await {
+            let (id, result) = match run {
+                Ok((id, Some(result))) => (id, result),
+                // The file was closed before the run
+                Ok((_, None)) => continue,
+                Err(error) => (error.id(), Err(format!("crashed: {error}"))),
+            };
+
+            let error = match result.and_then(|results| parse_result(&results)) {
+                Ok((mut diagnostics, notification)) => {
+                    plugins_result.diagnostic.append(&mut diagnostics);
+                    plugins_result.notification.extend(notification);
+                    None
+                }
+                Err(error) => Some(error),
+            };
+
+            let (name, path) = plugins.remove(&id).unwrap_or_default();
+            plugins_result.status.push(PluginStatus {
+                name,
+                uri: Url::from_file_path(path).ok(),
+                error,
+            });
+        }
+
         plugins_result
     }
 
-    fn execute(plugin: Plugin) -> String {
-        // Replace "your_program" with the actual binary you want to execute
-        let mut child = Command::new(plugin.path.clone())
+    fn track(&self, task: AbortHandle) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task);
+    }
+}
+
+fn parse_result(results: &CustomResult) -> Result<(Vec<Diagnostic>, Option<CustomParams>), String> {
+    match results.output_type {
+        TypesNotification::Diagnostic => from_str(results.data.as_str())
+            .map(|diagnostics| (diagnostics, None))
+            .map_err(|error| format!("invalid diagnostics: {error}")),
+        TypesNotification::Notification => from_str(results.data.as_str())
+            .map(|notification| (vec![], Some(notification)))
+            .map_err(|error| format!("invalid notification: {error}")),
+        TypesNotification::Nothing => Ok((vec![], None)),
+    }
+}
+
+/// A configured plugin with the process it keeps when it is persistent.
+struct PluginRunner {
+    plugin: Plugin,
+    // Also serializes the runs of a persistent plugin
+    persistent: Mutex<PersistentPlugin>,
+}
+
+impl PluginRunner {
+    /// Runs the plugin, `None` when the file was closed in the meantime.
+    async fn run(
+        self: Arc<Self>,
+        file: Url,
+        buffers: BufferSource,
+        state: OnState,
+    ) -> Option<Result<CustomResult, String>> {
+        let plugin = &self.plugin;
+
+        // Cancelled by a newer edit while waiting
+        if state == OnState::Change {
+            tokio::time::sleep(Duration::from_millis(plugin.debounce_ms)).await;
+        }
+
+        let structured = plugin.input == InputFormat::Structured;
+        let with_dirty_buffers = structured && plugin.dirty_buffers;
+        let (buffer, dirty_buffers) = buffers(with_dirty_buffers)?;
+        let mut arguments = plugin.arguments.clone();
+        arguments.push(Argument {
+            key: String::from("file"),
+            value: buffer.file.clone(),
+        });
+        let input = PluginInput {
+            arguments,
+            buffer: structured.then_some(buffer),
+            dirty_buffers: with_dirty_buffers.then_some(dirty_buffers),
+        };
+
+        Some(self.send(file, input, state).await)
+    }
+
+    async fn send(
+        &self,
+        file: Url,
+        input: PluginInput,
+        state: OnState,
+    ) -> Result<CustomResult, String> {
+        let plugin = &self.plugin;
+        let timeout = Duration::from_millis(plugin.timeout_ms);
+
+        if plugin.persistent {
+            let mut params = serde_json::to_value(input).map_err(|error| error.to_string())?;
+            params["uri"] = json!(file);
+            params["on"] = json!(state);
+            // Aborted along with the run, by a newer edit or on shutdown
+            let result = self
+                .persistent
+                .lock()
+                .await
+                .request(plugin, "fileChanged", params, timeout)
+                .await?;
+
+            serde_json::from_value(result).map_err(|error| format!("invalid result: {error}"))
+        } else {
+            let output = tokio::time::timeout(timeout, PluginRunner::execute(plugin, &input))
+                .await
+                .map_err(|_| format!("timed out after {timeout:?}"))??;
+
+            from_str(&output).map_err(|error| format!("invalid output: {error}"))
+        }
+    }
+
+    // The plugin is killed when the run is dropped on timeout
+    async fn execute(plugin: &Plugin, input: &PluginInput) -> Result<String, String> {
+        let mut child = Command::new(&plugin.path)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
-            .unwrap();
+            .map_err(|error| format!("could not be started: {error}"))?;
 
-        // Write data to the child process's stdin
+        // Write data to the child process's stdin, a plugin may exit without
+        // reading it so its output tells whether it failed
         if let Some(mut stdin) = child.stdin.take() {
-            let arguments = plugin.arguments;
-            let json_str = to_string(&arguments).unwrap();
-            stdin.write_all(json_str.as_bytes()).unwrap();
+            let json_str = match plugin.input {
+                InputFormat::Arguments => to_string(&input.arguments),
+                InputFormat::Structured => to_string(input),
+            }
+            .map_err(|error| error.to_string())?;
+            let _ = stdin.write_all(json_str.as_bytes()).await;
         }
 
         // Wait for the child process to finish and capture its stdout
-        match child.wait_with_output() {
-            Ok(output) => String::from_utf8(output.stdout).unwrap(),
-            Err(e) => e.to_string(),
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|error| error.to_string())?;
+        if !output.status.success() {
+            return Err(format!(
+                "exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
         }
+
+        String::from_utf8(output.stdout).map_err(|error| format!("invalid output: {error}"))
+    }
+}
+
+/// Process of a persistent plugin, restarted with a growing delay when it
+/// crashes.
+struct PersistentPlugin {
+    process: Option<PluginProcess>,
+    crashes: u32,
+    restart_at: Instant,
+    // Set on shutdown, runs still in flight must not start it again
+    stopped: bool,
+}
+
+impl PersistentPlugin {
+    fn new() -> PersistentPlugin {
+        PersistentPlugin {
+            process: None,
+            crashes: 0,
+            restart_at: Instant::now(),
+            stopped: false,
+        }
+    }
+
+    /// The running process, started again once the restart delay is over.
+    async fn process(&mut self, plugin: &Plugin) -> Option<&mut PluginProcess> {
+        if self.stopped {
+            return None;
+        }
+        if self.process.as_mut().is_some_and(PluginProcess::has_exited) {
+            warn!("Plugin {} exited", plugin.name);
+            self.crashed(plugin).await;
+        }
+
+        if self.process.is_none() && Instant::now() >= self.restart_at {
+            info!("Starting plugin {}", plugin.name);
+            let params = json!({
+                "name": plugin.name,
+                "arguments": plugin.arguments,
+            });
+            let timeout = Duration::from_millis(plugin.timeout_ms);
+            let started = tokio::time::timeout(timeout, PluginProcess::start(&plugin.path, params))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out after {timeout:?} on initialize"),
+                    ))
+                });
+            match started {
+                Ok(process) => self.process = Some(process),
+                Err(error) => {
+                    error!("Plugin {} could not be started: {error}", plugin.name);
+                    self.crashed(plugin).await;
+                }
+            }
+        }
+
+        self.process.as_mut()
+    }
+
+    async fn request(
+        &mut self,
+        plugin: &Plugin,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, String> {
+        self.process(plugin).await;
+        let Some(process) = self.process.as_mut() else {
+            if self.stopped {
+                return Err(String::from("stopped"));
+            }
+            return Err(format!(
+                "not running, restarted in {:?}",
+                self.restart_at.saturating_duration_since(Instant::now())
+            ));
+        };
+
+        let error = match tokio::time::timeout(timeout, process.request(method, params)).await {
+            Ok(Ok(Ok(result))) => {
+                self.crashes = 0;
+                return Ok(result);
+            }
+            Ok(Ok(Err(error))) => return Err(format!("answered {method} with an error: {error}")),
+            Ok(Err(error)) => format!("failed on {method}: {error}"),
+            Err(_) => format!("timed out after {timeout:?} on {method}"),
+        };
+
+        // The state of the plugin is unknown, so it is started again
+        self.crashed(plugin).await;
+        Err(error)
+    }
+
+    async fn crashed(&mut self, plugin: &Plugin) {
+        if let Some(process) = self.process.take() {
+            process.kill().await;
+        }
+
+        let delay = RESTART_DELAY
+            .saturating_mul(2_u32.saturating_pow(self.crashes))
+            .min(MAX_RESTART_DELAY);
+        self.crashes += 1;
+        self.restart_at = Instant::now() + delay;
+        info!("Plugin {} will be restarted in {delay:?}", plugin.name);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    const NOTHING: &str = r#"{"output_type": "Nothing", "data": ""}"#;
+
+    /// Writes a plugin running `script` in its own folder, and loads it with
+    /// the extra configuration `fields`. Returns the folder.
+    fn plugin(test: &str, script: &str, fields: &str) -> (PluginManager, PathBuf) {
+        let directory = std::env::temp_dir().join(format!("lever-{test}"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("plugin.sh");
+        fs::write(
+            &path,
+            format!("#!/bin/sh\ncd '{}'\n{script}", directory.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let manager = PluginManager::new();
+        let configuration = format!(
+            r#"[{{"name": "{test}", "path": "{}", "on": ["Save"], "arguments": [{{"key": "mode", "value": "check"}}], "state": true{fields}}}]"#,
+            path.display()
+        );
+        manager.load_plugins(None, &configuration);
+
+        (manager, directory)
+    }
+
+    /// Plugin recording its input in `input.json`.
+    fn recording_plugin(test: &str, fields: &str) -> (PluginManager, PathBuf) {
+        let script = format!("cat > input.json\necho '{NOTHING}'\n");
+        let (manager, directory) = plugin(test, &script, fields);
+
+        (manager, directory.join("input.json"))
+    }
+
+    /// Persistent plugin answering its requests with `responses`, in order.
+    fn persistent_plugin(test: &str, responses: &[Value]) -> PluginManager {
+        let messages: String = responses
+            .iter()
+            .map(|response| {
+                let content = response.to_string();
+                format!("Content-Length: {}\r\n\r\n{content}", content.len())
+            })
+            .collect();
+        // Exits once its input is closed on shutdown
+        let script = format!("printf '%s' '{messages}'\nwhile read -r _; do :; done\n");
+
+        plugin(test, &script, r#", "persistent": true"#).0
+    }
+
+    fn buffers() -> BufferSource {
+        let buffer = |file: &str, version| Buffer {
+            file: file.to_string(),
+            version,
+            source_code: format!("// {file}\n"),
+        };
+        Arc::new(move |with_dirty_buffers| {
+            let dirty_buffers = if with_dirty_buffers {
+                vec![buffer("/lib.js", Some(3))]
+            } else {
+                vec![]
+            };
+            Some((buffer("/main.js", Some(2)), dirty_buffers))
+        })
+    }
+
+    async fn run(manager: &PluginManager) -> Option<String> {
+        let file = Url::from_file_path("/main.js").unwrap();
+        let mut result = manager.run_plugins(&file, buffers(), &OnState::Save).await;
+        assert_eq!(result.status.len(), 1);
+
+        result.status.pop().unwrap().error
+    }
+
+    async fn run_recorded(manager: &PluginManager, input: &PathBuf) -> Value {
+        assert_eq!(run(manager).await, None);
+
+        from_str(&fs::read_to_string(input).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_arguments_input() {
+        let (manager, input) = recording_plugin("plugin-arguments", r#", "dirty_buffers": true"#);
+
+        assert_eq!(
+            run_recorded(&manager, &input).await,
+            json!([
+                {"key": "mode", "value": "check"},
+                {"key": "file", "value": "/main.js"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_structured_input() {
+        let (manager, input) = recording_plugin(
+            "plugin-structured",
+            r#", "input": "structured", "dirty_buffers": true"#,
+        );
+
+        assert_eq!(
+            run_recorded(&manager, &input).await,
+            json!({
+                "arguments": [
+                    {"key": "mode", "value": "check"},
+                    {"key": "file", "value": "/main.js"},
+                ],
+                "buffer": {"file": "/main.js", "version": 2, "source_code": "// /main.js\n"},
+                "dirty_buffers": [
+                    {"file": "/lib.js", "version": 3, "source_code": "// /lib.js\n"},
+                ],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_persistent_plugin() {
+        let manager = persistent_plugin(
+            "plugin-persistent",
+            &[
+                json!({"jsonrpc": "2.0", "id": 0, "result": {}}),
+                json!({"jsonrpc": "2.0", "id": 1, "result": from_str::<Value>(NOTHING).unwrap()}),
+            ],
+        );
+
+        assert_eq!(run(&manager).await, None);
+        manager.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_persistent_plugin_initialize_error() {
+        let manager = persistent_plugin(
+            "plugin-initialize-error",
+            &[json!({"jsonrpc": "2.0", "id": 0, "error": {"code": -32603, "message": "no"}})],
+        );
+
+        let error = run(&manager).await.unwrap();
+        assert!(error.starts_with("not running"), "{error}");
+        manager.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_runs() {
+        let manager = Arc::new(persistent_plugin(
+            "plugin-shutdown",
+            &[json!({"jsonrpc": "2.0", "id": 0, "result": {}})],
+        ));
+
+        // Never answered
+        let pending = tokio::spawn({
+            let manager = manager.clone();
+            async move { run(&manager).await }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tokio::time::timeout(Duration::from_secs(5), manager.shutdown())
+            .await
+            .unwrap();
+
+        let error = pending.await.unwrap().unwrap();
+        assert!(error.starts_with("crashed"), "{error}");
     }
 }
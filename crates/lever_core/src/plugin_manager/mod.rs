@@ -1,7 +1,9 @@
 mod manager;
 mod notification;
+mod process;
 
+pub use manager::Buffer;
+pub use manager::BufferSource;
 pub use manager::OnState;
 pub use manager::PluginManager;
-pub use manager::PluginsResult;
 pub use notification::CustomNotification;
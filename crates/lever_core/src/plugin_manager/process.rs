@@ -0,0 +1,180 @@
+use serde_json::{json, Value};
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+// Time given to a plugin to exit after `shutdown` before it is killed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
+/// A plugin process kept alive between runs. Messages are JSON-RPC, framed
+/// with `Content-Length` headers like LSP messages. A request can be dropped
+/// at any point, the bytes in transit are kept for the next one and its
+/// response is skipped.
+pub struct PluginProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    // Read and not parsed yet
+    input: Vec<u8>,
+    // Not written yet
+    output: Vec<u8>,
+    next_id: u64,
+}
+
+impl PluginProcess {
+    /// Starts the plugin and waits for its response to `initialize`, an
+    /// error answered by the plugin fails the start.
+    pub async fn start(path: &str, params: Value) -> io::Result<PluginProcess> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("plugin has no stdio"));
+        };
+
+        let mut process = PluginProcess {
+            child,
+            stdin,
+            stdout,
+            input: Vec::new(),
+            output: Vec::new(),
+            next_id: 0,
+        };
+        match process.request("initialize", params).await? {
+            Ok(_) => Ok(process),
+            Err(error) => Err(io::Error::other(format!(
+                "answered initialize with an error: {error}"
+            ))),
+        }
+    }
+
+    pub fn has_exited(&mut self) -> bool {
+        !matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Sends a request and waits for its response, messages that are not its
+    /// response are skipped. The inner error is the one the plugin answered.
+    pub async fn request(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> io::Result<Result<Value, Value>> {
+        let id = self.send_request(method, params).await?;
+
+        loop {
+            let mut message = self.read_message().await?;
+            let Some(message_id) = message.get("id").and_then(Value::as_u64) else {
+                continue;
+            };
+
+            if message_id == id {
+                return Ok(match message.get_mut("error") {
+                    Some(error) => Err(error.take()),
+                    None => Ok(message["result"].take()),
+                });
+            }
+            if let Some(error) = message.get("error") {
+                warn!("Plugin answered request {message_id} with an error: {error}");
+            }
+        }
+    }
+
+    /// Sends `shutdown` and closes the input of the plugin, it is killed if
+    /// it does not exit in time.
+    pub async fn shutdown(mut self) {
+        let _ = self.send_request("shutdown", Value::Null).await;
+        drop(self.stdin);
+
+        if tokio::time::timeout(SHUTDOWN_GRACE, self.child.wait())
+            .await
+            .is_err()
+        {
+            let _ = self.child.kill().await;
+        }
+    }
+
+    pub async fn kill(mut self) {
+        let _ = self.child.kill().await;
+    }
+
+    async fn send_request(&mut self, method: &str, params: Value) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let content = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let message = format!("Content-Length: {}\r\n\r\n{content}", content.len());
+        self.output.extend_from_slice(message.as_bytes());
+        self.write_output().await?;
+
+        Ok(id)
+    }
+
+    async fn write_output(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            // Unlike `write_all`, nothing is written when it is cancelled
+            let written = self.stdin.write(&self.output).await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.output.drain(..written);
+        }
+
+        self.stdin.flush().await
+    }
+
+    async fn read_message(&mut self) -> io::Result<Value> {
+        loop {
+            if let Some(message) = self.parse_message()? {
+                return Ok(message);
+            }
+
+            // Unlike `read_exact`, nothing is read when it is cancelled
+            let mut buffer = [0; 4096];
+            let read = self.stdout.read(&mut buffer).await?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.input.extend_from_slice(&buffer[..read]);
+        }
+    }
+
+    /// Takes the first message of the input, `None` until it is complete.
+    fn parse_message(&mut self) -> io::Result<Option<Value>> {
+        let Some(headers_end) = self
+            .input
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        else {
+            return Ok(None);
+        };
+
+        let content_length = String::from_utf8_lossy(&self.input[..headers_end])
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+            })?;
+
+        let content_start = headers_end + 4;
+        if self.input.len() < content_start + content_length {
+            return Ok(None);
+        }
+        let message: Vec<u8> = self.input.drain(..content_start + content_length).collect();
+
+        serde_json::from_slice(&message[content_start..])
+            .map(Some)
+            .map_err(io::Error::from)
+    }
+}
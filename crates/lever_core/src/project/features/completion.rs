@@ -12,13 +12,14 @@ use tower_lsp::lsp_types::{
 fn default_list(
     position: Position,
     query: &Arc<Mutex<impl SymbolTableQuery>>,
+    language_def: &LanguageDefinition,
 ) -> Vec<CompletionItem> {
     let mut items: Vec<CompletionItem> = Vec::new();
 
     for symbol in query.lock().unwrap().get_symbols_at_pos(position) {
         items.push(CompletionItem {
             label: symbol.name.clone(),
-            kind: get_symbol_completion_type(&symbol.name),
+            kind: get_symbol_completion_type(language_def, &symbol.kind),
             ..Default::default()
         });
     }
@@ -26,12 +27,13 @@ fn default_list(
     items
 }
 
-fn get_symbol_completion_type(symbol_kind: &str) -> Option<CompletionItemKind> {
+fn get_symbol_completion_type(
+    language_def: &LanguageDefinition,
+    symbol_kind: &str,
+) -> Option<CompletionItemKind> {
     Some(
-        LanguageDefinition::get()
-            .symbol_types
-            .iter()
-            .find(|symbol_type| symbol_type.name == symbol_kind)?
+        language_def
+            .get_symbol_def(symbol_kind)?
             .completion_type
             .get(),
     )
@@ -40,6 +42,7 @@ fn get_symbol_completion_type(symbol_kind: &str) -> Option<CompletionItemKind> {
 pub fn get_imported_list(
     uri: &Url,
     st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    language_def: &LanguageDefinition,
 ) -> Vec<CompletionItem> {
     let query = st_query.lock().unwrap();
 
@@ -50,10 +53,16 @@ pub fn get_imported_list(
         .map(|s| CompletionItem {
             label: s.name.clone(),
             label_details: Some(CompletionItemLabelDetails {
-                detail: Some(uri.path_segments().unwrap().last().unwrap().to_string()),
+                detail: Some(
+                    uri.path_segments()
+                        .unwrap()
+                        .next_back()
+                        .unwrap()
+                        .to_string(),
+                ),
                 description: None,
             }),
-            kind: get_symbol_completion_type(&s.kind),
+            kind: get_symbol_completion_type(language_def, &s.kind),
             ..Default::default()
         })
         .collect()
@@ -63,6 +72,7 @@ pub fn get_list(
     position: Position,
     ast_query: &Arc<Mutex<impl AstQuery>>,
     symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    language_def: &LanguageDefinition,
     context: Option<CompletionContext>,
 ) -> Option<Vec<CompletionItem>> {
     if let Some(context) = context {
@@ -101,5 +111,5 @@ pub fn get_list(
         }
     }
 
-    Some(default_list(position, symbol_table_query))
+    Some(default_list(position, symbol_table_query, language_def))
 }
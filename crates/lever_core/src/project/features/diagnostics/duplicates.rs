@@ -0,0 +1,40 @@
+use std::sync::{Arc, Mutex};
+
+use crate::project::{AstQuery, SymbolTableQuery};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Url,
+};
+
+use super::provider::DiagnosticProvider;
+
+/// Names defined more than once in the same scope.
+pub struct Duplicates {}
+
+impl DiagnosticProvider for Duplicates {
+    fn get_diagnostics(
+        uri: &Url,
+        _ast_query: &Arc<Mutex<impl AstQuery>>,
+        symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        symbol_table_query
+            .lock()
+            .unwrap()
+            .get_duplicate_symbols()
+            .into_iter()
+            .map(|(name, range, first_range)| {
+                Diagnostic::new(
+                    range,
+                    Some(DiagnosticSeverity::ERROR),
+                    Some(NumberOrString::String("duplicate".to_string())),
+                    Some("Symbol table".to_string()),
+                    format!("`{name}` is already defined in this scope"),
+                    Some(vec![DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), first_range),
+                        message: format!("first definition of `{name}`"),
+                    }]),
+                    None,
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,41 @@
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+
+/// Imports that lead back to the importing file. Not a `DiagnosticProvider`,
+/// since cycles are found in the file graph.
+pub struct ImportCycles {}
+
+impl ImportCycles {
+    /// `cycles` holds the range of each import in a cycle, with the chain of
+    /// files from the importing file back to itself.
+    pub fn get_diagnostics(cycles: Vec<(Range, Vec<Url>)>, forbidden: bool) -> Vec<Diagnostic> {
+        let severity = if forbidden {
+            DiagnosticSeverity::ERROR
+        } else {
+            DiagnosticSeverity::WARNING
+        };
+
+        cycles
+            .into_iter()
+            .map(|(range, chain)| {
+                let chain: Vec<&str> = chain
+                    .iter()
+                    .map(|url| {
+                        url.path_segments()
+                            .and_then(|mut s| s.next_back())
+                            .unwrap_or(url.as_str())
+                    })
+                    .collect();
+
+                Diagnostic::new(
+                    range,
+                    Some(severity),
+                    Some(NumberOrString::String("import-cycle".to_string())),
+                    Some("Imports".to_string()),
+                    format!("import cycle: {}", chain.join(" → ")),
+                    None,
+                    None,
+                )
+            })
+            .collect()
+    }
+}
@@ -1,6 +1,14 @@
+mod duplicates;
+mod import_cycles;
 mod import_errors;
 mod parse;
 mod provider;
+mod shadowing;
+mod undefined_symbols;
+mod unused;
 
+pub use import_cycles::ImportCycles;
 pub use import_errors::ImportErrors;
 pub use provider::{get_full, get_quick};
+pub use shadowing::Shadowing;
+pub use unused::Unused;
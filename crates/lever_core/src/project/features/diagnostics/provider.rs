@@ -2,7 +2,9 @@ use std::sync::{Arc, Mutex};
 
 use tower_lsp::lsp_types::{Diagnostic, Url};
 
+use super::duplicates::Duplicates;
 use super::parse::Parse;
+use super::undefined_symbols::UndefinedSymbols;
 
 use crate::project::{features::diagnostics::ImportErrors, AstQuery, SymbolTableQuery};
 
@@ -35,7 +37,9 @@ pub fn get_quick(
 ) -> Vec<Diagnostic> {
     diags![
         Parse::get_diagnostics(uri, ast_query, symbol_table_query),
-        ImportErrors::get_diagnostics(uri, ast_query, symbol_table_query)
+        ImportErrors::get_diagnostics(uri, ast_query, symbol_table_query),
+        UndefinedSymbols::get_diagnostics(uri, ast_query, symbol_table_query),
+        Duplicates::get_diagnostics(uri, ast_query, symbol_table_query)
     ]
 }
 
@@ -46,6 +50,8 @@ pub fn get_full(
 ) -> Vec<Diagnostic> {
     diags![
         Parse::get_diagnostics(uri, ast_query, symbol_table_query),
-        ImportErrors::get_diagnostics(uri, ast_query, symbol_table_query)
+        ImportErrors::get_diagnostics(uri, ast_query, symbol_table_query),
+        UndefinedSymbols::get_diagnostics(uri, ast_query, symbol_table_query),
+        Duplicates::get_diagnostics(uri, ast_query, symbol_table_query)
     ]
 }
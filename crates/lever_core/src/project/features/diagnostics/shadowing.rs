@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use crate::language_def::LanguageDefinition;
+use crate::project::{metadata::Symbol, SymbolTableQuery};
+use crate::settings::Settings;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Range,
+    Url,
+};
+
+/// Definitions hiding a symbol of an enclosing scope or of an imported file.
+/// Not a `DiagnosticProvider`, since it depends on the settings and the
+/// symbols of the imported files.
+pub struct Shadowing {}
+
+impl Shadowing {
+    pub fn get_diagnostics(
+        uri: &Url,
+        symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+        imported_symbols: &[(Url, Symbol)],
+        language_def: &LanguageDefinition,
+        settings: &Settings,
+    ) -> Vec<Diagnostic> {
+        let symbol_table_query = symbol_table_query.lock().unwrap();
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+
+        if settings.warn_outer_shadowing {
+            for (name, range, outer_range) in symbol_table_query.get_shadowed_symbols() {
+                diagnostics.push(new_diagnostic(
+                    range,
+                    format!("`{name}` shadows a definition of an outer scope"),
+                    Location::new(uri.clone(), outer_range),
+                    format!("shadowed definition of `{name}`"),
+                ));
+            }
+        }
+
+        if settings.warn_import_shadowing {
+            for symbol in symbol_table_query.get_all_symbols() {
+                if language_def.is_member_symbol(&symbol.kind) {
+                    continue;
+                }
+                if let Some((imported_uri, imported)) = imported_symbols
+                    .iter()
+                    .find(|(_, imported)| imported.name == symbol.name)
+                {
+                    diagnostics.push(new_diagnostic(
+                        symbol.def_range,
+                        format!("`{}` shadows an imported definition", symbol.name),
+                        Location::new(imported_uri.clone(), imported.def_range),
+                        format!("imported definition of `{}`", symbol.name),
+                    ));
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+fn new_diagnostic(
+    range: Range,
+    message: String,
+    location: Location,
+    related_message: String,
+) -> Diagnostic {
+    Diagnostic::new(
+        range,
+        Some(DiagnosticSeverity::WARNING),
+        Some(NumberOrString::String("shadowing".to_string())),
+        Some("Symbol table".to_string()),
+        message,
+        Some(vec![DiagnosticRelatedInformation {
+            location,
+            message: related_message,
+        }]),
+        None,
+    )
+}
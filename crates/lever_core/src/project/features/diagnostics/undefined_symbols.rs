@@ -0,0 +1,52 @@
+use std::sync::{Arc, Mutex};
+
+use crate::language_def::Symbol;
+use crate::project::{AstQuery, SymbolTableQuery, Visitable};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+
+use super::provider::DiagnosticProvider;
+
+/// Usages still unlinked after linking the imported files, and member
+/// usages missing from the field scope of their type.
+pub struct UndefinedSymbols {}
+
+impl DiagnosticProvider for UndefinedSymbols {
+    fn get_diagnostics(
+        _uri: &Url,
+        ast_query: &Arc<Mutex<impl AstQuery>>,
+        symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = {
+            let ast_query = ast_query.lock().unwrap();
+
+            ast_query
+                .visit_root()
+                .get_descendants()
+                .iter()
+                .map(|node| node.get())
+                .filter(|node| node.symbol == Symbol::Usage && node.linked_symbol.is_none())
+                .map(|node| {
+                    new_diagnostic(node.range, format!("undefined symbol `{}`", node.content))
+                })
+                .collect()
+        };
+
+        for (name, range) in symbol_table_query.lock().unwrap().get_undefined_members() {
+            diagnostics.push(new_diagnostic(range, format!("undefined member `{name}`")));
+        }
+
+        diagnostics
+    }
+}
+
+fn new_diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic::new(
+        range,
+        Some(DiagnosticSeverity::ERROR),
+        Some(NumberOrString::String("undefined".to_string())),
+        Some("Symbol table".to_string()),
+        message,
+        None,
+        None,
+    )
+}
@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+
+use crate::language_def::{LanguageDefinition, UnusedPolicy};
+use crate::project::SymbolTableQuery;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Range};
+
+/// Definitions without usages and imports without linked symbols, which
+/// editors fade. Not a `DiagnosticProvider`, since unused imports depend on
+/// the file graph.
+pub struct Unused {}
+
+impl Unused {
+    pub fn get_diagnostics(
+        symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+        language_def: &LanguageDefinition,
+        unused_imports: &[Range],
+    ) -> Vec<Diagnostic> {
+        let symbol_table_query = symbol_table_query.lock().unwrap();
+        let (top_level_symbols, _) = symbol_table_query.get_symbols_at_root();
+
+        let mut diagnostics: Vec<Diagnostic> = symbol_table_query
+            .get_all_symbols()
+            .into_iter()
+            .filter(|symbol| symbol.usages.is_empty())
+            .filter(|symbol| {
+                let policy = language_def
+                    .get_symbol_def(&symbol.kind)
+                    .map_or_else(UnusedPolicy::default, |symbol_def| symbol_def.unused);
+
+                match policy {
+                    UnusedPolicy::Report => true,
+                    UnusedPolicy::AllowTopLevel => !top_level_symbols
+                        .iter()
+                        .any(|top_level| top_level.def_range == symbol.def_range),
+                    UnusedPolicy::Allow => false,
+                }
+            })
+            .map(|symbol| {
+                new_diagnostic(symbol.def_range, format!("`{}` is never used", symbol.name))
+            })
+            .collect();
+
+        for range in unused_imports {
+            diagnostics.push(new_diagnostic(
+                *range,
+                String::from("none of the imported symbols are used"),
+            ));
+        }
+
+        diagnostics
+    }
+}
+
+fn new_diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String("unused".to_string())),
+        source: Some("Symbol table".to_string()),
+        message,
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        ..Default::default()
+    }
+}
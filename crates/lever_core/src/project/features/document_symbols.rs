@@ -0,0 +1,46 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{DocumentSymbol, SymbolKind};
+
+use crate::{
+    language_def::LanguageDefinition,
+    project::{metadata::SymbolTree, SymbolTableQuery},
+};
+
+pub fn get_document_symbols(
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    language_def: &LanguageDefinition,
+) -> Vec<DocumentSymbol> {
+    let symbol_tree = st_query.lock().unwrap().get_symbol_tree();
+
+    symbol_tree
+        .into_iter()
+        .map(|tree| to_document_symbol(tree, language_def))
+        .collect()
+}
+
+#[allow(deprecated)] // `DocumentSymbol::deprecated` has to be set
+fn to_document_symbol(tree: SymbolTree, language_def: &LanguageDefinition) -> DocumentSymbol {
+    let kind = language_def
+        .get_symbol_def(&tree.symbol.kind)
+        .map_or(SymbolKind::VARIABLE, |symbol_def| {
+            symbol_def.get_symbol_kind()
+        });
+
+    let children: Vec<DocumentSymbol> = tree
+        .children
+        .into_iter()
+        .map(|child| to_document_symbol(child, language_def))
+        .collect();
+
+    DocumentSymbol {
+        name: tree.symbol.name,
+        detail: Some(tree.symbol.kind),
+        kind,
+        tags: None,
+        deprecated: None,
+        range: tree.range,
+        selection_range: tree.symbol.def_range,
+        children: (!children.is_empty()).then_some(children),
+    }
+}
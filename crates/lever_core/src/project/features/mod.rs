@@ -1,3 +1,4 @@
 pub mod completion;
 pub mod diagnostics;
+pub mod document_symbols;
 pub mod semantic_tokens;
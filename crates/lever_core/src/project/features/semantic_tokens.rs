@@ -1,5 +1,5 @@
 use crate::{
-    language_def::{self, LanguageDefinition},
+    language_def::LanguageDefinition,
     lsp_mappings::HighlightType,
     project::{AstQuery, SymbolTableQuery, Visitable},
     utils,
@@ -8,7 +8,9 @@ use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
 };
-use tower_lsp::lsp_types::{SemanticToken, SemanticTokens, SemanticTokensResult};
+use tower_lsp::lsp_types::{
+    Range, SemanticToken, SemanticTokenType, SemanticTokens, SemanticTokensResult,
+};
 use tree_sitter::Node;
 
 pub struct ColorData {
@@ -23,11 +25,27 @@ pub fn get_tokens(
     symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
     ts_tree: &tree_sitter::Tree,
     source_code: &str,
+    language_def: &LanguageDefinition,
+    token_types: &[SemanticTokenType],
+    external_symbols: Vec<(Range, HighlightType)>,
 ) -> SemanticTokensResult {
+    let semantic_token_types_map = get_semantic_token_map(token_types);
+
     //Getting ast data
-    let mut array = get_keyword_color_data(&ts_tree.root_node(), source_code);
-    array.append(&mut get_symbols_color_data(symbol_table_query));
-    array.append(&mut get_ast_color_data(ast_query));
+    let mut array = get_keyword_color_data(&ts_tree.root_node(), source_code, language_def);
+    array.append(&mut get_symbols_color_data(
+        symbol_table_query,
+        language_def,
+        &semantic_token_types_map,
+    ));
+    array.append(&mut get_ast_color_data(
+        ast_query,
+        &semantic_token_types_map,
+    ));
+    array.append(&mut get_external_color_data(
+        external_symbols,
+        &semantic_token_types_map,
+    ));
     //sort line
 
     array.sort_by_key(|token| token.line);
@@ -91,21 +109,22 @@ pub fn get_tokens(
     })
 }
 
-fn get_semantic_token_map() -> HashMap<String, usize> {
+fn get_semantic_token_map(token_types: &[SemanticTokenType]) -> HashMap<String, usize> {
     let mut semantic_token_types_map = HashMap::new();
 
-    for (i, token_type) in language_def::LanguageDefinition::get_semantic_token_types()
-        .iter()
-        .enumerate()
-    {
+    for (i, token_type) in token_types.iter().enumerate() {
         semantic_token_types_map.insert(token_type.as_str().to_string(), i);
     }
 
     semantic_token_types_map
 }
 
-pub fn get_keyword_color_data(root_node: &tree_sitter::Node, source_code: &str) -> Vec<ColorData> {
-    let keywords = crate::language_def::LanguageDefinition::get_keywords();
+pub fn get_keyword_color_data(
+    root_node: &tree_sitter::Node,
+    source_code: &str,
+    language_def: &LanguageDefinition,
+) -> Vec<ColorData> {
+    let keywords = language_def.keyword_set();
 
     let mut cursor = root_node.walk();
     let mut to_visit = root_node.children(&mut cursor).collect::<Vec<Node>>();
@@ -128,17 +147,24 @@ pub fn get_keyword_color_data(root_node: &tree_sitter::Node, source_code: &str)
     color_data
 }
 
-pub fn get_symbols_color_data(st_query: &Arc<Mutex<impl SymbolTableQuery>>) -> Vec<ColorData> {
-    let semantic_token_types_map = get_semantic_token_map();
-
+pub fn get_symbols_color_data(
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    language_def: &LanguageDefinition,
+    semantic_token_types_map: &HashMap<String, usize>,
+) -> Vec<ColorData> {
     let symbols = st_query.lock().unwrap().get_all_symbols();
 
     let mut color_data = vec![];
     for symbol in symbols {
-        let highlight_type = get_symbol_highlight_type(&symbol.kind);
-        let node_type = *semantic_token_types_map
-            .get(highlight_type.get().as_str())
-            .unwrap() as u32;
+        let Some(symbol_def) = language_def.get_symbol_def(&symbol.kind) else {
+            continue;
+        };
+        let highlight_type = &symbol_def.highlight_type;
+        // Types added by a rules reload are not part of the legend
+        let Some(node_type) = semantic_token_types_map.get(highlight_type.get().as_str()) else {
+            continue;
+        };
+        let node_type = *node_type as u32;
 
         let def_range = symbol.def_range;
         color_data.push(ColorData {
@@ -148,7 +174,8 @@ pub fn get_symbols_color_data(st_query: &Arc<Mutex<impl SymbolTableQuery>>) -> V
             node_type,
         });
 
-        for usage in symbol.usages {
+        // Usages from other files are colored by those files
+        for usage in symbol.usages.iter().filter(|usage| usage.file_id.is_none()) {
             let range = usage.range;
             color_data.push(ColorData {
                 line: range.start.line,
@@ -162,36 +189,48 @@ pub fn get_symbols_color_data(st_query: &Arc<Mutex<impl SymbolTableQuery>>) -> V
     color_data
 }
 
-fn get_symbol_highlight_type(symbol_kind: &str) -> HighlightType {
-    LanguageDefinition::get()
-        .symbol_types
-        .iter()
-        .find(|symbol_type| symbol_type.name == symbol_kind)
-        .unwrap()
-        .highlight_type
-        .clone()
-}
-
-pub fn get_ast_color_data(ast_query: &Arc<Mutex<impl AstQuery>>) -> Vec<ColorData> {
-    let semantic_token_types_map = get_semantic_token_map();
-
+pub fn get_ast_color_data(
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    semantic_token_types_map: &HashMap<String, usize>,
+) -> Vec<ColorData> {
     let ast_query = ast_query.lock().unwrap();
 
     let mut color_data = vec![];
     for visit_node in ast_query.visit_root().get_descendants() {
         let node = visit_node.get();
 
-        if let Some(semantic_token_type) = &node.semantic_token_type {
+        if let Some(node_type) = node
+            .semantic_token_type
+            .as_ref()
+            .and_then(|t| semantic_token_types_map.get(t.get().as_str()))
+        {
             color_data.push(ColorData {
                 line: node.range.start.line,
                 start: node.range.start.character,
                 length: node.range.end.character - node.range.start.character,
-                node_type: *semantic_token_types_map
-                    .get(semantic_token_type.get().as_str())
-                    .unwrap() as u32,
+                node_type: *node_type as u32,
             });
         }
     }
 
     color_data
 }
+
+/// Symbols defined in other files, possibly in another language, which are
+/// highlighted with the definition of the file they come from.
+pub fn get_external_color_data(
+    external_symbols: Vec<(Range, HighlightType)>,
+    semantic_token_types_map: &HashMap<String, usize>,
+) -> Vec<ColorData> {
+    external_symbols
+        .into_iter()
+        .filter_map(|(range, highlight_type)| {
+            Some(ColorData {
+                line: range.start.line,
+                start: range.start.character,
+                length: range.end.character - range.start.character,
+                node_type: *semantic_token_types_map.get(highlight_type.get().as_str())? as u32,
+            })
+        })
+        .collect()
+}
@@ -1,29 +1,38 @@
-use std::env;
 use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use tower_lsp::lsp_types::{
-    self, CompletionContext, CompletionItem, Diagnostic, Position, SemanticTokensResult,
-    TextDocumentContentChangeEvent, Url,
+    self, CompletionContext, CompletionItem, Diagnostic, DocumentSymbol, Position,
+    SemanticTokenType, SemanticTokensResult, TextDocumentContentChangeEvent, Url,
 };
 use tree_sitter::{InputEdit, Parser, Tree};
 
-use super::features::{completion, diagnostics, semantic_tokens};
+use super::features::{completion, diagnostics, document_symbols, semantic_tokens};
 use super::metadata::{
     AstEditor, AstManager, AstQuery, SymbolId, SymbolTableEditor, SymbolTableManager, Visitable,
 };
 
-use crate::language_def::{Import, LanguageDefinition};
+use crate::language_def::{Import, LanguageDefinition, LanguageEntry};
+use crate::lsp_mappings::HighlightType;
 use crate::project::workspace;
 use crate::utils;
+use crate::Translator;
 
 pub struct File {
     pub uri: Url,
     pub source_code: String,
+    /// Version of the editor buffer, `None` when the content was read from
+    /// disk
+    pub version: Option<i32>,
+    /// Version of the editor buffer when it was last saved, unsaved changes
+    /// clear it
+    pub saved_version: Option<i32>,
     pub tree: Tree,
     pub symbol_table_manager: Arc<Mutex<SymbolTableManager>>,
     pub ast_manager: Arc<Mutex<AstManager>>,
+    pub language_def: Arc<LanguageDefinition>,
+    translator: Arc<dyn Translator>,
     parser: tree_sitter::Parser,
 }
 
@@ -31,23 +40,34 @@ pub struct File {
 impl Debug for File {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("File")
-            .field("name", &self.uri.path_segments().unwrap().last().unwrap())
+            .field(
+                "name",
+                &self.uri.path_segments().unwrap().next_back().unwrap(),
+            )
             .finish_non_exhaustive()
     }
 }
 
 impl File {
-    pub fn new(uri: Url, source_code: &str, tree_sitter_language: tree_sitter::Language) -> File {
+    pub fn new(uri: Url, source_code: &str, language: &LanguageEntry) -> File {
         let mut parser = Parser::new();
-        parser.set_language(tree_sitter_language).unwrap();
+        parser.set_language(language.treesitter_language).unwrap();
 
         let tree = parser.parse(source_code, None).unwrap();
 
-        let ast_manager = Arc::new(Mutex::new(AstManager::new(source_code, tree.clone())));
+        let (language_def, translator) = language.current();
+        let ast_manager = Arc::new(Mutex::new(AstManager::new(
+            source_code,
+            tree.clone(),
+            translator.as_ref(),
+        )));
 
         let symbol_table_manager = {
             let mut ast_manager = ast_manager.lock().unwrap();
-            Arc::new(Mutex::new(SymbolTableManager::new(ast_manager.get_ast())))
+            Arc::new(Mutex::new(SymbolTableManager::new(
+                ast_manager.get_ast(),
+                &language_def,
+            )))
         };
 
         debug!("\nAST:\n{}", ast_manager.lock().unwrap());
@@ -56,9 +76,13 @@ impl File {
         File {
             uri,
             source_code: source_code.to_string(),
+            version: None,
+            saved_version: None,
             tree,
             symbol_table_manager,
             ast_manager,
+            language_def,
+            translator,
             parser,
         }
     }
@@ -87,85 +111,102 @@ impl File {
                     .replace_range(start_byte..old_end_byte, &change.text);
 
                 text = self.source_code.clone();
+                self.ast_manager.lock().unwrap().edit(&edit);
                 let tree = &mut self.tree;
                 tree.edit(&edit);
                 old_tree = Some(tree);
             } else {
                 // If change.range is None, change.text represents the whole file
+                self.source_code = change.text.clone();
                 text = change.text.clone();
             }
 
             self.tree = self.parser.parse(text, old_tree).unwrap();
         }
 
+        self.build(true);
+    }
+
+    /// Rebuilds the AST and symbol table from the current syntax tree, which is
+    /// needed when the language definition changes.
+    pub fn rebuild(&mut self, language: &LanguageEntry) {
+        (self.language_def, self.translator) = language.current();
+        self.build(false);
+    }
+
+    /// Builds the AST again from the syntax tree, only translating the edited
+    /// parts of it when `edited`.
+    fn build(&mut self, edited: bool) {
         let mut ast_manager = self.ast_manager.lock().unwrap();
         let mut symbol_table_manager = self.symbol_table_manager.lock().unwrap();
 
-        ast_manager.update(&self.source_code, self.tree.clone());
-        symbol_table_manager.update(ast_manager.get_ast());
+        if edited {
+            ast_manager.update(
+                &self.source_code,
+                self.tree.clone(),
+                self.translator.as_ref(),
+            );
+        } else {
+            *ast_manager = AstManager::new(
+                &self.source_code,
+                self.tree.clone(),
+                self.translator.as_ref(),
+            );
+        }
+        symbol_table_manager.update(ast_manager.get_ast(), &self.language_def);
 
-        debug!("\nAST:\n{}", ast_manager);
-        debug!("\nSymbol Table:\n{}", symbol_table_manager);
+        trace!("\nAST:\n{}", ast_manager);
+        trace!("\nSymbol Table:\n{}", symbol_table_manager);
     }
 
-    pub fn get_import_paths(&self) -> Vec<Result<(workspace::Import, PathBuf), lsp_types::Range>> {
+    /// Resolves the imports of the file, library imports are searched in
+    /// `library_directories` before the directories of the rules. Imports
+    /// that are not found come with the paths they were searched at.
+    pub fn get_import_paths(&self, library_directories: &[PathBuf]) -> Vec<workspace::ImportPath> {
         let ast = self.ast_manager.lock().unwrap();
         let visit = ast.visit_root();
         let nodes = visit.get_descendants();
 
         nodes
             .iter()
-            .filter_map(|node| match node.get().import {
-                Import::Local => {
-                    let file_name = node.get().content.clone();
-                    let mut curr_path = self.uri.to_file_path().unwrap();
-                    curr_path.pop(); // Get dir
-
-                    curr_path.push(file_name);
-
-                    if curr_path.exists() {
-                        Some(Ok((workspace::Import::Local, curr_path)))
-                    } else {
-                        Some(Err(node.get().range))
-                    }
-                }
-                Import::Library => {
-                    let lib_paths = &LanguageDefinition::get().language.library_paths;
-                    let file_name = &node.get().content;
-
-                    if let Some(path) = lib_paths.env_variables.iter().find_map(|var| {
-                        if let Ok(existing_var) = env::var(var) {
-                            if let Ok(mut path) = existing_var.parse::<PathBuf>() {
-                                path.push(file_name);
-                                if path.exists() {
-                                    return Some(path);
-                                }
-                            }
+            .filter_map(|node| {
+                let range = node.get().range;
+                let edge = |import| workspace::ImportEdge { import, range };
+
+                match node.get().import {
+                    Import::Local => {
+                        let file_name = node.get().content.clone();
+                        let mut curr_path = self.uri.to_file_path().unwrap();
+                        curr_path.pop(); // Get dir
+
+                        curr_path.push(file_name);
+
+                        if curr_path.exists() {
+                            Some(Ok((edge(workspace::Import::Local), curr_path)))
+                        } else {
+                            Some(Err((range, vec![curr_path])))
                         }
-                        None
-                    }) {
-                        return Some(Ok((workspace::Import::Library, path)));
                     }
-
-                    if cfg!(target_os = "windows") {
-                        if let Some(path) = utils::find_lib(&lib_paths.windows, file_name) {
-                            return Some(Ok((workspace::Import::Library, path)));
-                        }
-                    } else if cfg!(target_os = "macos") {
-                        if let Some(path) = utils::find_lib(&lib_paths.macos, file_name) {
-                            return Some(Ok((workspace::Import::Library, path)));
-                        }
-                    } else if cfg!(target_os = "linux") {
-                        if let Some(path) = utils::find_lib(&lib_paths.linux, file_name) {
-                            return Some(Ok((workspace::Import::Library, path)));
-                        }
-                    } else {
-                        error!("Unsupported platform for imports, all file imports will fail.");
+                    Import::Library => {
+                        let lib_paths = &self.language_def.language.library_paths;
+                        let file_name = &node.get().content;
+                        let mut directories = library_directories.to_vec();
+                        directories.extend(lib_paths.directories());
+
+                        Some(
+                            utils::find_lib(&directories, file_name)
+                                .map(|path| (edge(workspace::Import::Library), path))
+                                .ok_or_else(|| {
+                                    let paths = directories
+                                        .iter()
+                                        .map(|directory| directory.join(file_name))
+                                        .collect();
+                                    (range, paths)
+                                }),
+                        )
                     }
-
-                    Some(Err(node.get().range))
+                    Import::None => None,
                 }
-                Import::None => None,
             })
             .collect()
     }
@@ -187,12 +228,17 @@ impl File {
             position,
             &self.ast_manager,
             &self.symbol_table_manager,
+            &self.language_def,
             context,
         )
     }
 
     pub fn get_import_completion_list(&self) -> Vec<CompletionItem> {
-        completion::get_imported_list(&self.uri, &self.symbol_table_manager)
+        completion::get_imported_list(&self.uri, &self.symbol_table_manager, &self.language_def)
+    }
+
+    pub fn get_document_symbols(&self) -> Vec<DocumentSymbol> {
+        document_symbols::get_document_symbols(&self.symbol_table_manager, &self.language_def)
     }
 
     pub fn get_symbol_id_at_pos(&self, position: Position) -> Option<SymbolId> {
@@ -203,12 +249,19 @@ impl File {
         node.get().linked_symbol.clone()
     }
 
-    pub fn get_semantic_tokens(&self) -> SemanticTokensResult {
+    pub fn get_semantic_tokens(
+        &self,
+        token_types: &[SemanticTokenType],
+        external_symbols: Vec<(lsp_types::Range, HighlightType)>,
+    ) -> SemanticTokensResult {
         semantic_tokens::get_tokens(
             &self.ast_manager,
             &self.symbol_table_manager,
             &self.tree,
             &self.source_code,
+            &self.language_def,
+            token_types,
+            external_symbols,
         )
     }
 }
@@ -1,4 +1,5 @@
 mod rules_translator;
 mod tree;
 
-pub use tree::{Ast, Node, NodeKind, Translator, VisitNode, Visitable};
+pub use rules_translator::RulesTranslator;
+pub use tree::{Ast, Node, NodeKind, ReusableNodes, Translator, VisitNode, Visitable};
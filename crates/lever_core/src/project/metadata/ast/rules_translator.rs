@@ -1,6 +1,9 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
 use indextree::{Arena, NodeId};
 
-use super::{tree::Translator, Ast, Node, NodeKind};
+use super::{tree::Translator, Ast, Node, NodeKind, ReusableNodes};
 use crate::{
     language_def::{
         Child, DirectOrRule, Import, LanguageDefinition, Rule, Symbol, TreesitterNodeQuery,
@@ -8,39 +11,69 @@ use crate::{
     lsp_mappings::HighlightType,
 };
 
+/// Interprets the rules of a language definition, used when no generated
+/// translator matches the rules, e.g. after they were reloaded.
 pub struct RulesTranslator {
-    arena: Arena<Node>,
-    language_def: &'static LanguageDefinition,
+    language_def: Arc<LanguageDefinition>,
 }
 
 impl Translator for RulesTranslator {
-    fn translate(&mut self, source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast {
-        let root_id = self.build(source_code, syntax_tree);
+    fn translate(&self, source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast {
+        let mut translation = Translation {
+            arena: Arena::new(),
+            language_def: &self.language_def,
+            reusable: ReusableNodes::default(),
+        };
+        let root_rule = self.language_def.rule_with_name("Root").unwrap();
+        let root_id = translation.parse(root_rule, source_code, &syntax_tree.root_node());
 
-        Ast::initialize(self.arena.clone(), root_id)
+        translation
+            .reusable
+            .finish(translation.arena, root_id, syntax_tree)
+    }
+
+    fn retranslate(&self, source_code: &str, syntax_tree: tree_sitter::Tree, ast: &mut Ast) {
+        let (arena, reusable) = ReusableNodes::take(ast, &syntax_tree);
+        let mut translation = Translation {
+            arena,
+            language_def: &self.language_def,
+            reusable,
+        };
+        let root_rule = self.language_def.rule_with_name("Root").unwrap();
+        let root_id = translation.parse(root_rule, source_code, &syntax_tree.root_node());
+
+        *ast = translation
+            .reusable
+            .finish(translation.arena, root_id, syntax_tree);
     }
 }
 
 impl RulesTranslator {
-    pub fn new() -> RulesTranslator {
-        RulesTranslator {
-            arena: Arena::new(),
-            language_def: LanguageDefinition::get(),
-        }
+    pub fn new(language_def: Arc<LanguageDefinition>) -> RulesTranslator {
+        RulesTranslator { language_def }
     }
+}
 
-    fn build(&mut self, source_code: &str, syntax_tree: tree_sitter::Tree) -> NodeId {
-        let root_rule = self.language_def.rule_with_name("Root").unwrap();
-
-        self.parse(&root_rule.clone(), source_code, &syntax_tree.root_node())
-    }
+struct Translation<'a> {
+    arena: Arena<Node>,
+    language_def: &'a LanguageDefinition,
+    reusable: ReusableNodes,
+}
 
+impl<'a> Translation<'a> {
     fn parse(
         &mut self,
-        current_rule: &Rule,
+        current_rule: &'a Rule,
         source_code: &str,
         current_ts_node: &tree_sitter::Node,
     ) -> NodeId {
+        if let Some(node_id) =
+            self.reusable
+                .reuse(&mut self.arena, &current_rule.node_name, current_ts_node)
+        {
+            return node_id;
+        }
+
         let mut cursor = current_ts_node.walk();
         let children: Vec<tree_sitter::Node> = current_ts_node.children(&mut cursor).collect();
 
@@ -61,24 +94,45 @@ impl RulesTranslator {
         }
 
         for child in &current_rule.children {
-            self.query_parse_child(source_code, &children, child, current_node_id);
+            self.query_parse_child(
+                source_code,
+                current_ts_node,
+                &children,
+                child,
+                current_node_id,
+            );
         }
 
-        for child in &LanguageDefinition::get().global_ast_rules {
-            self.query_parse_child(source_code, &children, child, current_node_id);
+        for child in &self.language_def.global_ast_rules {
+            self.query_parse_child(
+                source_code,
+                current_ts_node,
+                &children,
+                child,
+                current_node_id,
+            );
         }
 
+        self.reusable.record(current_ts_node, current_node_id);
         current_node_id
     }
 
     fn query_parse_child(
         &mut self,
         source_code: &str,
+        current_ts_node: &tree_sitter::Node,
         children: &[tree_sitter::Node],
-        child: &Child,
+        child: &'a Child,
         current_node_id: NodeId,
     ) {
-        let (query, node_or_rule) = (&child.query, &child.rule);
+        let query = &child.query;
+
+        if let TreesitterNodeQuery::Query(pattern) = query {
+            for ts_node in self.query_captures(source_code, current_ts_node, pattern) {
+                self.append_child(source_code, &ts_node, &ts_node, child, current_node_id);
+            }
+            return;
+        }
 
         for (i, ts_node) in children.iter().enumerate() {
             let target_node = if let TreesitterNodeQuery::Path(path) = query {
@@ -88,7 +142,8 @@ impl RulesTranslator {
 
                 let mut current_ts_node = *ts_node;
                 if !match &path[0] {
-                    TreesitterNodeQuery::Path(_) => unimplemented!(), // TODO
+                    // Rejected by the validation of the rules
+                    TreesitterNodeQuery::Path(_) | TreesitterNodeQuery::Query(_) => false,
                     TreesitterNodeQuery::Kind(kind) => current_ts_node.kind() == kind,
                     TreesitterNodeQuery::Field(name) => {
                         current_ts_node
@@ -108,7 +163,7 @@ impl RulesTranslator {
                         .enumerate()
                         .filter(|node| node.1.is_named())
                         .find(|(i, ts_node)| match element {
-                            TreesitterNodeQuery::Path(_) => unimplemented!(),
+                            TreesitterNodeQuery::Path(_) | TreesitterNodeQuery::Query(_) => false,
                             TreesitterNodeQuery::Kind(kind) => ts_node.kind() == kind,
                             TreesitterNodeQuery::Field(name) => {
                                 ts_node.parent().unwrap().field_name_for_child(*i as u32)
@@ -131,36 +186,72 @@ impl RulesTranslator {
                     ts_node.parent().unwrap().field_name_for_child(i as u32) == Some(name)
                 }
                 TreesitterNodeQuery::Path(_) => true,
+                TreesitterNodeQuery::Query(_) => false,
             } {
-                match node_or_rule {
-                    DirectOrRule::Direct(node_kind) => {
-                        if ts_node.has_error() {
-                            current_node_id.append(
-                                self.new_error_node(source_code, ts_node, None),
-                                &mut self.arena,
-                            );
-                        }
-
-                        current_node_id.append(
-                            self.new_node(
-                                source_code,
-                                NodeKind::Node(node_kind.clone()),
-                                &target_node,
-                                Symbol::None,
-                                Import::None,
-                                child.highlight_type.clone(),
-                            ),
-                            &mut self.arena,
-                        );
-                    }
-                    DirectOrRule::Rule(name) => {
-                        let rule = self.language_def.rule_with_name(name).unwrap().clone();
-                        current_node_id.append(
-                            self.parse(&rule, source_code, &target_node),
-                            &mut self.arena,
-                        );
-                    }
+                self.append_child(source_code, ts_node, &target_node, child, current_node_id);
+            }
+        }
+    }
+
+    /// Nodes captured by a `Query` pattern below `ts_node`, in document order.
+    /// Captures starting with `_` are only used by the pattern itself.
+    fn query_captures<'tree>(
+        &self,
+        source_code: &str,
+        ts_node: &tree_sitter::Node<'tree>,
+        pattern: &str,
+    ) -> Vec<tree_sitter::Node<'tree>> {
+        let Some(query) = self.language_def.get_query(pattern) else {
+            error!("Query was not compiled: {pattern}");
+            return vec![];
+        };
+        let capture_names = query.capture_names();
+
+        let mut cursor = tree_sitter::QueryCursor::new();
+        let mut nodes: Vec<tree_sitter::Node> = cursor
+            .matches(&query, *ts_node, source_code.as_bytes())
+            .flat_map(|query_match| query_match.captures.iter())
+            .filter(|capture| !capture_names[capture.index as usize].starts_with('_'))
+            .map(|capture| capture.node)
+            .collect();
+
+        nodes.sort_by_key(|node| (node.start_byte(), Reverse(node.end_byte()), node.id()));
+        nodes.dedup_by_key(|node| node.id());
+        nodes
+    }
+
+    fn append_child(
+        &mut self,
+        source_code: &str,
+        ts_node: &tree_sitter::Node,
+        target_node: &tree_sitter::Node,
+        child: &'a Child,
+        current_node_id: NodeId,
+    ) {
+        match &child.rule {
+            DirectOrRule::Direct(node_kind) => {
+                if ts_node.has_error() {
+                    current_node_id.append(
+                        self.new_error_node(source_code, ts_node, None),
+                        &mut self.arena,
+                    );
                 }
+
+                current_node_id.append(
+                    self.new_node(
+                        source_code,
+                        NodeKind::Node(node_kind.clone()),
+                        target_node,
+                        Symbol::None,
+                        Import::None,
+                        child.highlight_type.clone(),
+                    ),
+                    &mut self.arena,
+                );
+            }
+            DirectOrRule::Rule(name) => {
+                let rule = self.language_def.rule_with_name(name).unwrap();
+                current_node_id.append(self.parse(rule, source_code, target_node), &mut self.arena);
             }
         }
     }
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 use indextree::{Arena, NodeId};
@@ -8,13 +9,11 @@ use tower_lsp::lsp_types::{Position, Range};
 
 use crate::project::metadata::SymbolId;
 use crate::{
-    language_def::{self, Import, Symbol},
+    language_def::{Import, LanguageDefinition, Symbol},
     lsp_mappings::HighlightType,
     utils,
 };
 
-use super::rules_translator::RulesTranslator;
-
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Operator {
     Add,
@@ -50,12 +49,6 @@ pub enum NodeKind {
     Error(Option<String>),
 }
 
-impl NodeKind {
-    pub fn is_scope_node(&self) -> bool {
-        language_def::LanguageDefinition::get_scope_nodes().contains(self)
-    }
-}
-
 #[derive(Debug, PartialEq, Clone)]
 pub struct Node {
     pub kind: NodeKind,
@@ -95,11 +88,11 @@ impl Node {
 pub trait Visitable {
     fn get(&self) -> &Node;
     fn get_id(&self) -> NodeId;
-    fn get_children(&self) -> Vec<VisitNode>;
-    fn get_descendants(&self) -> Vec<VisitNode>;
-    fn get_child_of_kind(&self, kind: NodeKind) -> Option<VisitNode>;
-    fn get_subscopes(&self) -> Vec<VisitNode>;
-    fn get_node_at_position(&self, position: Position) -> Option<VisitNode>;
+    fn get_children(&self) -> Vec<VisitNode<'_>>;
+    fn get_descendants(&self) -> Vec<VisitNode<'_>>;
+    fn get_child_of_kind(&self, kind: NodeKind) -> Option<VisitNode<'_>>;
+    fn get_subscopes(&self, language_def: &LanguageDefinition) -> Vec<VisitNode<'_>>;
+    fn get_node_at_position(&self, position: Position) -> Option<VisitNode<'_>>;
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -112,6 +105,19 @@ impl<'a> VisitNode<'a> {
     pub const fn new(arena: &'a Arena<Node>, node_id: NodeId) -> VisitNode<'a> {
         VisitNode { arena, id: node_id }
     }
+
+    /// Same as `Visitable::get`, but borrows the AST instead of the visitor.
+    pub fn node(self) -> &'a Node {
+        self.arena.get(self.id).unwrap().get()
+    }
+
+    /// Same as `Visitable::get_children`, but borrows the AST instead of the
+    /// visitor, which is needed to return children from wrappers.
+    pub fn children(self) -> impl Iterator<Item = VisitNode<'a>> {
+        self.id
+            .children(self.arena)
+            .map(move |id| VisitNode::new(self.arena, id))
+    }
 }
 
 impl Visitable for VisitNode<'_> {
@@ -119,21 +125,21 @@ impl Visitable for VisitNode<'_> {
         self.arena.get(self.id).unwrap().get()
     }
 
-    fn get_children(&self) -> Vec<VisitNode> {
+    fn get_children(&self) -> Vec<VisitNode<'_>> {
         self.id
             .children(self.arena)
             .map(|id| VisitNode::new(self.arena, id))
             .collect::<Vec<VisitNode>>()
     }
 
-    fn get_descendants(&self) -> Vec<VisitNode> {
+    fn get_descendants(&self) -> Vec<VisitNode<'_>> {
         self.id
             .descendants(self.arena)
             .map(|id| VisitNode::new(self.arena, id))
             .collect::<Vec<VisitNode>>()
     }
 
-    fn get_child_of_kind(&self, kind: NodeKind) -> Option<VisitNode> {
+    fn get_child_of_kind(&self, kind: NodeKind) -> Option<VisitNode<'_>> {
         let id = self
             .id
             .children(self.arena)
@@ -142,14 +148,14 @@ impl Visitable for VisitNode<'_> {
         Some(VisitNode::new(self.arena, id))
     }
 
-    fn get_subscopes(&self) -> Vec<VisitNode> {
+    fn get_subscopes(&self, language_def: &LanguageDefinition) -> Vec<VisitNode<'_>> {
         self.get_children()
             .into_iter()
-            .filter(|child| child.get().kind.is_scope_node())
+            .filter(|child| language_def.is_scope_node(&child.get().kind))
             .collect::<Vec<VisitNode>>()
     }
 
-    fn get_node_at_position(&self, position: Position) -> Option<VisitNode> {
+    fn get_node_at_position(&self, position: Position) -> Option<VisitNode<'_>> {
         let mut child_id = self.id;
 
         loop {
@@ -170,14 +176,166 @@ impl Visitable for VisitNode<'_> {
     }
 }
 
-pub trait Translator {
-    fn translate(&mut self, source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast;
+/// Builds the AST of a file from its syntax tree. Implemented by
+/// `RulesTranslator` and by the code generated with `rules_translator!`.
+pub trait Translator: Send + Sync {
+    fn translate(&self, source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast;
+
+    /// Translates an edited syntax tree into `ast`, moving the rule nodes of
+    /// `ast` whose syntax nodes the edits left untouched instead of
+    /// translating them again. `ast` must come from the same translator.
+    fn retranslate(&self, source_code: &str, syntax_tree: tree_sitter::Tree, ast: &mut Ast) {
+        *ast = self.translate(source_code, syntax_tree);
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Ast {
     arena: Arena<Node>,
     root_id: NodeId,
+    // Syntax node each rule node was translated from, see `ReusableNodes`
+    syntax_ids: HashMap<NodeId, usize>,
+    // Keeps the syntax nodes of `syntax_ids` alive, so their ids are not
+    // given to other nodes
+    syntax_tree: Option<tree_sitter::Tree>,
+    // Edits made to the source code since `syntax_tree` was parsed
+    edits: Vec<tree_sitter::InputEdit>,
+    // Roots of the subtrees the last translation moved from the previous AST
+    reused: HashSet<NodeId>,
+}
+
+impl fmt::Debug for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ast")
+            .field("arena", &self.arena)
+            .field("root_id", &self.root_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Rule nodes of a previous AST that a translation can move into the next
+/// one. Tree-sitter shares the subtrees an edit did not touch between the
+/// old and the new syntax tree, so a syntax node with the same id outside
+/// of the changed ranges has the same content and only its position may
+/// have changed.
+#[derive(Default)]
+pub struct ReusableNodes {
+    previous_root: Option<NodeId>,
+    changed_ranges: Vec<tree_sitter::Range>,
+    nodes: HashMap<usize, NodeId>,
+    syntax_ids: HashMap<NodeId, usize>,
+    recorded: HashMap<NodeId, usize>,
+    reused: HashSet<NodeId>,
+    _previous_tree: Option<tree_sitter::Tree>,
+}
+
+impl ReusableNodes {
+    /// Takes the nodes of `previous` to translate `syntax_tree`, an edited
+    /// version of its syntax tree. The next AST is built in its arena.
+    pub fn take(
+        previous: &mut Ast,
+        syntax_tree: &tree_sitter::Tree,
+    ) -> (Arena<Node>, ReusableNodes) {
+        let syntax_ids = std::mem::take(&mut previous.syntax_ids);
+        let previous_tree = previous.syntax_tree.take();
+        // Edited on a copy, editing the tree itself could free nodes of
+        // `syntax_ids`
+        let changed_ranges = previous_tree.as_ref().map(|tree| {
+            let mut edited_tree = tree.clone();
+            for edit in previous.edits.drain(..) {
+                edited_tree.edit(&edit);
+            }
+            edited_tree.changed_ranges(syntax_tree).collect()
+        });
+        let reusable = ReusableNodes {
+            previous_root: Some(previous.root_id),
+            changed_ranges: changed_ranges.unwrap_or_default(),
+            nodes: syntax_ids
+                .iter()
+                .map(|(node_id, syntax_id)| (*syntax_id, *node_id))
+                .collect(),
+            syntax_ids,
+            recorded: HashMap::new(),
+            reused: HashSet::new(),
+            _previous_tree: previous_tree,
+        };
+
+        (std::mem::take(&mut previous.arena), reusable)
+    }
+
+    /// Detaches the node of kind `kind` translated from `syntax_node` in the
+    /// previous AST, with its positions moved to those of `syntax_node`. The
+    /// links of its subtree are left for the symbol table to check.
+    pub fn reuse(
+        &mut self,
+        arena: &mut Arena<Node>,
+        kind: &str,
+        syntax_node: &tree_sitter::Node,
+    ) -> Option<NodeId> {
+        let node_id = *self.nodes.get(&syntax_node.id())?;
+        if self.changed_ranges.iter().any(|range| {
+            range.start_byte < syntax_node.end_byte() && syntax_node.start_byte() < range.end_byte
+        }) {
+            return None;
+        }
+        if !matches!(&arena[node_id].get().kind, NodeKind::Node(name) if name == kind) {
+            return None;
+        }
+
+        // Ancestors would be missing this node
+        for ancestor_id in node_id.ancestors(arena) {
+            if let Some(syntax_id) = self.syntax_ids.remove(&ancestor_id) {
+                self.nodes.remove(&syntax_id);
+            }
+        }
+        node_id.detach(arena);
+
+        let from = arena[node_id].get().range.start;
+        let to = utils::point_to_pos(syntax_node.start_position());
+        let descendants: Vec<NodeId> = node_id.descendants(arena).collect();
+        for descendant_id in descendants {
+            let node = arena[descendant_id].get_mut();
+            node.range.start = utils::move_position(node.range.start, from, to);
+            node.range.end = utils::move_position(node.range.end, from, to);
+
+            if let Some(syntax_id) = self.syntax_ids.remove(&descendant_id) {
+                self.nodes.remove(&syntax_id);
+                self.recorded.insert(descendant_id, syntax_id);
+            }
+        }
+        self.recorded.insert(node_id, syntax_node.id());
+        self.reused.insert(node_id);
+
+        Some(node_id)
+    }
+
+    /// Records the syntax node a new rule node was translated from.
+    pub fn record(&mut self, syntax_node: &tree_sitter::Node, node_id: NodeId) {
+        self.recorded.insert(node_id, syntax_node.id());
+    }
+
+    /// Removes the previous nodes that were not reused.
+    pub fn finish(
+        self,
+        mut arena: Arena<Node>,
+        root_id: NodeId,
+        syntax_tree: tree_sitter::Tree,
+    ) -> Ast {
+        if let Some(previous_root) = self.previous_root {
+            if previous_root != root_id {
+                previous_root.remove_subtree(&mut arena);
+            }
+        }
+
+        Ast {
+            arena,
+            root_id,
+            syntax_ids: self.recorded,
+            syntax_tree: Some(syntax_tree),
+            edits: vec![],
+            reused: self.reused,
+        }
+    }
 }
 
 impl fmt::Display for Ast {
@@ -187,13 +345,32 @@ impl fmt::Display for Ast {
 }
 
 impl Ast {
-    pub const fn initialize(arena: Arena<Node>, root_id: NodeId) -> Ast {
-        Ast { arena, root_id }
+    pub fn initialize(arena: Arena<Node>, root_id: NodeId) -> Ast {
+        Ast {
+            arena,
+            root_id,
+            syntax_ids: HashMap::new(),
+            syntax_tree: None,
+            edits: vec![],
+            reused: HashSet::new(),
+        }
     }
 
-    pub fn new(source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast {
-        let translator: &mut dyn Translator = &mut RulesTranslator::new();
-        translator.translate(source_code, syntax_tree)
+    /// Records an edit of the source code, to be given before translating the
+    /// edited syntax tree again.
+    pub fn edit(&mut self, edit: &tree_sitter::InputEdit) {
+        self.edits.push(*edit);
+    }
+
+    /// Whether the last translation moved a node from the previous AST, with
+    /// its whole subtree.
+    pub fn is_reused(&self, node_id: NodeId) -> bool {
+        !self.reused.is_empty()
+            && self.arena.get(node_id).is_some()
+            && !node_id.is_removed(&self.arena)
+            && node_id
+                .ancestors(&self.arena)
+                .any(|id| self.reused.contains(&id))
     }
 
     pub fn link_symbol(&mut self, symbol_id: SymbolId, range: Range) {
@@ -203,6 +380,20 @@ impl Ast {
         node.linked_symbol = Some(symbol_id);
     }
 
+    /// Removes the links to symbols of another file.
+    pub fn unlink_file(&mut self, file_id: petgraph::prelude::NodeIndex) {
+        for node in self.arena.iter_mut().filter(|node| !node.is_removed()) {
+            let node = node.get_mut();
+            if node
+                .linked_symbol
+                .as_ref()
+                .is_some_and(|symbol_id| symbol_id.get_file_id() == Some(file_id))
+            {
+                node.linked_symbol = None;
+            }
+        }
+    }
+
     pub fn get_node_at_position(&mut self, position: Position) -> NodeId {
         let mut child_id = self.root_id;
 
@@ -219,7 +410,7 @@ impl Ast {
         }
     }
 
-    pub const fn visit_root(&self) -> VisitNode {
+    pub const fn visit_root(&self) -> VisitNode<'_> {
         VisitNode::new(&self.arena, self.root_id)
     }
 
@@ -1,18 +1,29 @@
 use core::fmt;
 
+use petgraph::prelude::NodeIndex;
 use tower_lsp::lsp_types::Range;
+use tree_sitter::InputEdit;
 
 use super::ast::VisitNode;
 
-use super::{Ast, SymbolId};
+use super::{Ast, SymbolId, Translator};
 
 pub trait AstEditor {
-    fn update(&mut self, content: &str, syntax_tree: tree_sitter::Tree);
+    /// Translates an edited version of the syntax tree of the AST, with the
+    /// translator the AST was built with.
+    fn update(
+        &mut self,
+        content: &str,
+        syntax_tree: tree_sitter::Tree,
+        translator: &dyn Translator,
+    );
+    fn edit(&mut self, edit: &InputEdit);
     fn link_symbol(&mut self, symbol_id: SymbolId, range: Range);
+    fn unlink_file(&mut self, file_id: NodeIndex);
 }
 
 pub trait AstQuery {
-    fn visit_root(&self) -> VisitNode;
+    fn visit_root(&self) -> VisitNode<'_>;
 }
 
 #[derive(Debug, Clone)]
@@ -21,8 +32,12 @@ pub struct AstManager {
 }
 
 impl AstManager {
-    pub fn new(source_code: &str, tree: tree_sitter::Tree) -> AstManager {
-        let ast = Ast::new(source_code, tree);
+    pub fn new(
+        source_code: &str,
+        tree: tree_sitter::Tree,
+        translator: &dyn Translator,
+    ) -> AstManager {
+        let ast = translator.translate(source_code, tree);
         AstManager { ast }
     }
 
@@ -38,17 +53,30 @@ impl fmt::Display for AstManager {
 }
 
 impl AstQuery for AstManager {
-    fn visit_root(&self) -> VisitNode {
+    fn visit_root(&self) -> VisitNode<'_> {
         self.ast.visit_root()
     }
 }
 
 impl AstEditor for AstManager {
-    fn update(&mut self, content: &str, syntax_tree: tree_sitter::Tree) {
-        *self = AstManager::new(content, syntax_tree);
+    fn update(
+        &mut self,
+        content: &str,
+        syntax_tree: tree_sitter::Tree,
+        translator: &dyn Translator,
+    ) {
+        translator.retranslate(content, syntax_tree, &mut self.ast);
+    }
+
+    fn edit(&mut self, edit: &InputEdit) {
+        self.ast.edit(edit);
     }
 
     fn link_symbol(&mut self, symbol_id: SymbolId, range: Range) {
         self.ast.link_symbol(symbol_id, range);
     }
+
+    fn unlink_file(&mut self, file_id: NodeIndex) {
+        self.ast.unlink_file(file_id);
+    }
 }
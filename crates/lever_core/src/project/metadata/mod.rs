@@ -4,7 +4,9 @@ mod st_manager;
 mod symbol;
 mod symbol_table;
 
-pub use ast::{Ast, Node, NodeKind, Translator, VisitNode, Visitable};
+pub use ast::{
+    Ast, Node, NodeKind, ReusableNodes, RulesTranslator, Translator, VisitNode, Visitable,
+};
 pub use ast_manager::{AstEditor, AstManager, AstQuery};
 pub use st_manager::{SymbolTableEditor, SymbolTableManager, SymbolTableQuery};
-pub use symbol::{Symbol, SymbolId, Usage};
+pub use symbol::{StableSymbolId, Symbol, SymbolId, SymbolPath, SymbolTree, Usage};
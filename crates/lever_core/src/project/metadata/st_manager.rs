@@ -1,19 +1,24 @@
 use core::fmt;
 use std::fmt::Debug;
 
-use super::symbol::SymbolId;
+use super::symbol::{SymbolId, SymbolPath};
 use super::symbol_table::{ScopeId, SymbolTable};
-use super::{Ast, Symbol};
+use super::{Ast, Symbol, SymbolTree};
+use crate::language_def::LanguageDefinition;
 
 use indextree::NodeId;
+use petgraph::prelude::NodeIndex;
 use tower_lsp::lsp_types::{Position, Range};
 
 use super::symbol_table::Actions;
 
 pub trait SymbolTableEditor {
-    fn update(&mut self, ast: &mut Ast);
+    fn update(&mut self, ast: &mut Ast, language_def: &LanguageDefinition);
+    fn remove_external_usages(&mut self, file_id: NodeIndex);
+    fn remove_external_types(&mut self, file_id: NodeIndex);
 }
 
+#[allow(dead_code)]
 pub trait SymbolTableQuery {
     fn get_symbols_at_pos(&self, position: Position) -> Vec<Symbol>;
     fn get_symbols_at_root(&self) -> (Vec<Symbol>, NodeId);
@@ -23,6 +28,15 @@ pub trait SymbolTableQuery {
     fn get_symbol(&self, symbol_id: SymbolId) -> Option<&Symbol>;
     fn get_symbol_mut(&mut self, symbol_id: SymbolId) -> Option<&mut Symbol>;
     fn get_unlinked_symbols(&self) -> Vec<(String, Range)>;
+    fn get_undefined_members(&self) -> Vec<(String, Range)>;
+    fn get_duplicate_symbols(&self) -> Vec<(String, Range, Range)>;
+    fn get_shadowed_symbols(&self) -> Vec<(String, Range, Range)>;
+    fn get_symbol_tree(&self) -> Vec<SymbolTree>;
+    fn get_symbol_path(&self, symbol_id: &SymbolId) -> Option<SymbolPath>;
+    /// Id of the symbol with a path, in the table of its own file.
+    fn get_symbol_id(&self, path: &SymbolPath) -> Option<SymbolId>;
+    /// Name of every symbol of the file, with its id.
+    fn get_symbol_names(&self) -> &[(String, SymbolId)];
 }
 
 #[derive(Debug, Clone)]
@@ -31,8 +45,8 @@ pub struct SymbolTableManager {
 }
 
 impl SymbolTableManager {
-    pub fn new(ast: &mut Ast) -> SymbolTableManager {
-        let symbol_table = SymbolTable::new(ast);
+    pub fn new(ast: &mut Ast, language_def: &LanguageDefinition) -> SymbolTableManager {
+        let symbol_table = SymbolTable::new(ast, language_def);
         SymbolTableManager { symbol_table }
     }
 }
@@ -78,10 +92,46 @@ impl SymbolTableQuery for SymbolTableManager {
     fn get_unlinked_symbols(&self) -> Vec<(String, Range)> {
         self.symbol_table.get_unlinked_symbols()
     }
+
+    fn get_undefined_members(&self) -> Vec<(String, Range)> {
+        self.symbol_table.get_undefined_members()
+    }
+
+    fn get_duplicate_symbols(&self) -> Vec<(String, Range, Range)> {
+        self.symbol_table.get_duplicate_symbols()
+    }
+
+    fn get_shadowed_symbols(&self) -> Vec<(String, Range, Range)> {
+        self.symbol_table.get_shadowed_symbols()
+    }
+
+    fn get_symbol_tree(&self) -> Vec<SymbolTree> {
+        self.symbol_table.get_symbol_tree()
+    }
+
+    fn get_symbol_path(&self, symbol_id: &SymbolId) -> Option<SymbolPath> {
+        self.symbol_table.get_symbol_path(symbol_id)
+    }
+
+    fn get_symbol_id(&self, path: &SymbolPath) -> Option<SymbolId> {
+        self.symbol_table.get_symbol_id(path)
+    }
+
+    fn get_symbol_names(&self) -> &[(String, SymbolId)] {
+        self.symbol_table.get_symbol_names()
+    }
 }
 
 impl SymbolTableEditor for SymbolTableManager {
-    fn update(&mut self, ast: &mut Ast) {
-        *self = SymbolTableManager::new(ast);
+    fn update(&mut self, ast: &mut Ast, language_def: &LanguageDefinition) {
+        self.symbol_table.update(ast, language_def);
+    }
+
+    fn remove_external_usages(&mut self, file_id: NodeIndex) {
+        self.symbol_table.remove_external_usages(file_id);
+    }
+
+    fn remove_external_types(&mut self, file_id: NodeIndex) {
+        self.symbol_table.remove_external_types(file_id);
     }
 }
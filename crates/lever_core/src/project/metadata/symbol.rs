@@ -1,8 +1,11 @@
 use petgraph::prelude::NodeIndex;
-use tower_lsp::lsp_types::Range;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, Url};
 
 use super::symbol_table::ScopeId;
 
+/// Position of a symbol in the symbol tables, only valid until its file is
+/// rebuilt, see `StableSymbolId` otherwise.
 #[derive(Debug, PartialEq, Clone)]
 pub struct SymbolId {
     pub file_id: Option<petgraph::prelude::NodeIndex>,
@@ -28,6 +31,23 @@ impl SymbolId {
     }
 }
 
+/// Symbol of a file named by the symbols whose field scopes enclose it and
+/// its own name, e.g. `["Point", "x"]`. Symbols of a file with the same
+/// names are told apart by their order in the file.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub struct SymbolPath {
+    pub names: Vec<String>,
+    pub disambiguator: usize,
+}
+
+/// Identity of a symbol that survives rebuilds of the files, unlike
+/// `SymbolId`, so it can be kept across edits or sent to the client.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub struct StableSymbolId {
+    pub uri: Url,
+    pub path: SymbolPath,
+}
+
 #[derive(Debug, Clone)]
 pub struct Usage {
     pub file_id: Option<NodeIndex>,
@@ -77,6 +97,15 @@ impl Symbol {
     }
 }
 
+/// A symbol with the symbols of its field scope, see `SymbolTableQuery::get_symbol_tree`.
+#[derive(Debug, Clone)]
+pub struct SymbolTree {
+    pub symbol: Symbol,
+    /// Range of the field scope, or the definition when there is none
+    pub range: Range,
+    pub children: Vec<SymbolTree>,
+}
+
 impl std::fmt::Display for Symbol {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         fmt.write_str(
@@ -1,13 +1,14 @@
 use super::ast::{Ast, Visitable};
 
-use crate::language_def;
+use crate::language_def::{self, LanguageDefinition};
 use crate::project::metadata::NodeKind;
 
 use indextree::{Arena, NodeId};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use tower_lsp::lsp_types::{Position, Range};
 
-use super::{symbol::Usage, Node, Symbol, SymbolId};
+use super::{symbol::Usage, Node, Symbol, SymbolId, SymbolPath, SymbolTree};
 
 pub type ScopeId = NodeId;
 
@@ -16,8 +17,22 @@ pub struct SymbolTable {
     arena: Arena<ScopeSymbolTable>,
     pub root_id: Option<ScopeId>,
     undefined_list: Vec<(String, Range)>,
+    // Member usages whose type has a field scope without that member
+    undefined_member_list: Vec<(String, Range)>,
+    // Name, redefinition and first definition in the same scope
+    duplicate_list: Vec<(String, Range, Range)>,
+    // Name, definition and the definition it hides from an outer scope
+    shadowed_list: Vec<(String, Range, Range)>,
+    // Scope of each AST node opening one, the root node included. Scopes of
+    // nodes the translation reused are kept when the AST is updated
+    scope_nodes: HashMap<NodeId, ScopeId>,
+    paths: HashMap<SymbolPath, (ScopeId, usize)>,
+    symbol_paths: HashMap<(ScopeId, usize), SymbolPath>,
+    // Name of every symbol, searched without cloning the symbols
+    names: Vec<(String, SymbolId)>,
 }
 
+#[allow(dead_code)]
 pub trait Actions {
     fn get_symbol(&self, id: SymbolId) -> Option<&Symbol>;
     fn get_symbol_mut(&mut self, id: SymbolId) -> Option<&mut Symbol>;
@@ -29,6 +44,13 @@ pub trait Actions {
     fn get_symbol_at_pos(&self, name: String, position: Position) -> Option<&Symbol>;
     fn rename_symbol(&mut self, id: usize, new_name: String);
     fn get_unlinked_symbols(&self) -> Vec<(String, Range)>;
+    fn get_undefined_members(&self) -> Vec<(String, Range)>;
+    fn get_duplicate_symbols(&self) -> Vec<(String, Range, Range)>;
+    fn get_shadowed_symbols(&self) -> Vec<(String, Range, Range)>;
+    fn get_symbol_tree(&self) -> Vec<SymbolTree>;
+    fn get_symbol_path(&self, id: &SymbolId) -> Option<SymbolPath>;
+    fn get_symbol_id(&self, path: &SymbolPath) -> Option<SymbolId>;
+    fn get_symbol_names(&self) -> &[(String, SymbolId)];
 }
 
 impl Actions for SymbolTable {
@@ -132,20 +154,161 @@ impl Actions for SymbolTable {
     fn get_unlinked_symbols(&self) -> Vec<(String, Range)> {
         self.undefined_list.clone()
     }
+
+    fn get_undefined_members(&self) -> Vec<(String, Range)> {
+        self.undefined_member_list.clone()
+    }
+
+    fn get_duplicate_symbols(&self) -> Vec<(String, Range, Range)> {
+        self.duplicate_list.clone()
+    }
+
+    fn get_shadowed_symbols(&self) -> Vec<(String, Range, Range)> {
+        self.shadowed_list.clone()
+    }
+
+    fn get_symbol_tree(&self) -> Vec<SymbolTree> {
+        self.root_id
+            .map_or_else(Vec::new, |root_id| self.get_scope_tree(root_id))
+    }
+
+    fn get_symbol_path(&self, id: &SymbolId) -> Option<SymbolPath> {
+        self.symbol_paths
+            .get(&(id.symbol_table_id, id.index))
+            .cloned()
+    }
+
+    fn get_symbol_id(&self, path: &SymbolPath) -> Option<SymbolId> {
+        let (scope_id, index) = self.paths.get(path)?;
+        Some(SymbolId::new(None, *scope_id, *index))
+    }
+
+    fn get_symbol_names(&self) -> &[(String, SymbolId)] {
+        &self.names
+    }
 }
 
 impl SymbolTable {
-    pub fn new(ast: &mut Ast) -> SymbolTable {
+    pub fn new(ast: &mut Ast, language_def: &LanguageDefinition) -> SymbolTable {
         let mut table = SymbolTable::default();
-
-        table.root_id = Some(table.parse_scope(ast.visit_root().get_id(), ast.get_arena()));
-        table.parse_usages(ast.get_arena());
-        table.parse_types(ast.visit_root().get_id(), ast.get_arena());
-        table.parse_member_usages(ast.visit_root().get_id(), ast.get_arena());
+        table.update(ast, language_def);
 
         table
     }
 
+    /// Updates the table once `ast` was translated again. Scopes of the nodes
+    /// the translation reused are kept with the links of their usages, only
+    /// the other scopes are parsed again. Types, member usages, positions,
+    /// shadowing, paths and names are still resolved for the whole file.
+    pub fn update(&mut self, ast: &mut Ast, language_def: &LanguageDefinition) {
+        let kept = self.remove_edited_scopes(ast);
+        let root_node_id = ast.visit_root().get_id();
+        let arena = ast.get_arena();
+
+        let root_id = match kept.get(&root_node_id) {
+            Some(scope_id) => *scope_id,
+            None => self.parse_scope(root_node_id, arena, language_def, &kept),
+        };
+        self.root_id = Some(root_id);
+
+        self.parse_usages(root_node_id, arena, &kept);
+        self.relink_outer_usages(arena, &kept);
+        self.parse_types(arena);
+        self.parse_member_usages(arena);
+        self.parse_positions(arena);
+        self.parse_shadowing(language_def);
+        self.parse_paths();
+        self.parse_names();
+    }
+
+    /// Removes the scopes whose nodes were translated again, returning the
+    /// kept scopes by node.
+    fn remove_edited_scopes(&mut self, ast: &Ast) -> HashMap<NodeId, ScopeId> {
+        let (kept, edited): (HashMap<NodeId, ScopeId>, HashMap<NodeId, ScopeId>) = self
+            .scope_nodes
+            .drain()
+            .partition(|(node_id, _)| ast.is_reused(*node_id));
+        let edited: HashSet<ScopeId> = edited.into_values().collect();
+
+        if !edited.is_empty() {
+            // Scopes below a kept scope are kept too
+            for scope_id in kept.values() {
+                if self.arena[*scope_id]
+                    .parent()
+                    .is_some_and(|parent_id| edited.contains(&parent_id))
+                {
+                    scope_id.detach(&mut self.arena);
+                }
+            }
+            if let Some(root_id) = self.root_id.take() {
+                root_id.remove_subtree(&mut self.arena);
+            }
+        }
+
+        self.scope_nodes.clone_from(&kept);
+        kept
+    }
+
+    /// Removes the usages recorded by another file, before it is linked again.
+    pub fn remove_external_usages(&mut self, file_id: petgraph::prelude::NodeIndex) {
+        for scope in self.arena.iter_mut() {
+            for symbol in &mut scope.get_mut().symbols {
+                symbol.usages.retain(|usage| usage.file_id != Some(file_id));
+            }
+        }
+    }
+
+    /// Removes the types defined in another file, before it is linked again.
+    pub fn remove_external_types(&mut self, file_id: petgraph::prelude::NodeIndex) {
+        for scope in self.arena.iter_mut() {
+            for symbol in &mut scope.get_mut().symbols {
+                if symbol
+                    .type_symbol
+                    .as_ref()
+                    .is_some_and(|type_id| type_id.get_file_id() == Some(file_id))
+                {
+                    symbol.type_symbol = None;
+                }
+            }
+        }
+    }
+
+    /// Symbols of a scope, with the symbols of scopes that are not the field
+    /// scope of a symbol, like blocks, lifted into it.
+    fn get_scope_tree(&self, scope_id: ScopeId) -> Vec<SymbolTree> {
+        let scope = self.arena.get(scope_id).unwrap().get();
+
+        let mut trees: Vec<SymbolTree> = scope
+            .symbols
+            .iter()
+            .map(|symbol| match symbol.field_scope_id {
+                Some(field_scope_id) => SymbolTree {
+                    symbol: symbol.clone(),
+                    range: self.arena.get(field_scope_id).unwrap().get().range,
+                    children: self.get_scope_tree(field_scope_id),
+                },
+                None => SymbolTree {
+                    symbol: symbol.clone(),
+                    range: symbol.def_range,
+                    children: vec![],
+                },
+            })
+            .collect();
+
+        for child_scope_id in scope_id.children(&self.arena) {
+            if !scope
+                .symbols
+                .iter()
+                .any(|symbol| symbol.field_scope_id == Some(child_scope_id))
+            {
+                trees.append(&mut self.get_scope_tree(child_scope_id));
+            }
+        }
+
+        trees.sort_by_key(|tree| tree.symbol.def_range.start);
+        trees
+    }
+
     fn get_scope_id(&self, position: Position) -> Option<ScopeId> {
         self._get_scope_id(position, self.root_id?)
     }
@@ -167,17 +330,25 @@ impl SymbolTable {
         self.root_id
     }
 
-    fn parse_scope(&mut self, node_id: NodeId, ast_arena: &mut Arena<Node>) -> ScopeId {
-        let table = ScopeSymbolTable::new(ast_arena.get(node_id).unwrap().get().range);
+    fn parse_scope(
+        &mut self,
+        node_id: NodeId,
+        ast_arena: &mut Arena<Node>,
+        language_def: &LanguageDefinition,
+        kept: &HashMap<NodeId, ScopeId>,
+    ) -> ScopeId {
+        let table = ScopeSymbolTable::new(node_id, ast_arena.get(node_id).unwrap().get().range);
         let current_table_node_id = self.arena.new_node(table);
+        self.scope_nodes.insert(node_id, current_table_node_id);
 
         let mut queue: Vec<NodeId> = node_id.children(ast_arena).collect();
+        let mut overloadable: HashSet<usize> = HashSet::new();
 
         while let Some(node_id) = queue.pop() {
             let symbol_index = if let crate::language_def::Symbol::Init {
                 kind,
                 name_node,
-                type_node: _,
+                type_node,
             } = &ast_arena.get(node_id).unwrap().get().symbol
             {
                 debug!(
@@ -192,20 +363,38 @@ impl SymbolTable {
                         ast_arena.get(*id).unwrap().get().kind == NodeKind::Node(name_node.clone())
                     })
                     .unwrap();
+                let type_node_id = type_node.as_ref().and_then(|type_node| {
+                    let type_node_id = node_id.children(ast_arena).find(|id| {
+                        ast_arena.get(*id).unwrap().get().kind == NodeKind::Node(type_node.clone())
+                    });
+                    if type_node_id.is_none() {
+                        error!("Failed to parse type of symbol {kind}. This is caused by a problem within the Lever rules file.");
+                    }
+                    type_node_id
+                });
 
                 let name_node = ast_arena.get(name_node_id).unwrap().get();
 
                 let symbol = Symbol::new(name_node.content.clone(), kind.clone(), name_node.range);
 
-                let symbols = &mut self
-                    .arena
-                    .get_mut(current_table_node_id)
-                    .unwrap()
-                    .get_mut()
-                    .symbols;
-                symbols.push(symbol);
+                let table = self.arena.get_mut(current_table_node_id).unwrap().get_mut();
+                table.symbols.push(symbol);
+                table.name_nodes.push(name_node_id);
+                table.usage_nodes.push(vec![]);
+
+                let index = table.symbols.len() - 1;
+                if let Some(type_node_id) = type_node_id {
+                    table.typed_symbols.push((index, type_node_id));
+                }
+                if let NodeKind::Node(rule_name) = &ast_arena.get(node_id).unwrap().get().kind {
+                    if language_def
+                        .rule_with_name(rule_name)
+                        .is_some_and(|rule| rule.allow_overloading)
+                    {
+                        overloadable.insert(index);
+                    }
+                }
 
-                let index = symbols.len() - 1;
                 ast_arena
                     .get_mut(name_node_id)
                     .unwrap()
@@ -217,8 +406,11 @@ impl SymbolTable {
                 None
             };
 
-            if ast_arena.get(node_id).unwrap().get().kind.is_scope_node() {
-                let subtable = self.parse_scope(node_id, ast_arena);
+            if language_def.is_scope_node(&ast_arena.get(node_id).unwrap().get().kind) {
+                let subtable = match kept.get(&node_id) {
+                    Some(scope_id) => *scope_id,
+                    None => self.parse_scope(node_id, ast_arena, language_def, kept),
+                };
 
                 if let Some(i) = symbol_index {
                     self.arena
@@ -235,199 +427,365 @@ impl SymbolTable {
             }
         }
 
+        self.parse_duplicates(current_table_node_id, &overloadable);
+
         current_table_node_id
     }
 
-    fn parse_usages(&mut self, arena: &mut Arena<Node>) {
-        for node in arena
-            .iter_mut()
-            .filter(|node| matches!(node.get().symbol, language_def::Symbol::Usage))
-        {
-            let node = node.get_mut();
-            let symbol_name = &node.content;
+    /// Definitions of a name already defined earlier in the same scope. Both
+    /// definitions may share the name when their rules allow overloading and
+    /// they are of the same kind.
+    fn parse_duplicates(&mut self, scope_id: ScopeId, overloadable: &HashSet<usize>) {
+        let table = self.arena.get_mut(scope_id).unwrap().get_mut();
+        let symbols = &table.symbols;
+
+        let mut indexes: Vec<usize> = (0..symbols.len()).collect();
+        indexes.sort_by_key(|i| symbols[*i].def_range.start);
+
+        // Earlier definitions of each name
+        let mut definitions: HashMap<&str, Vec<usize>> = HashMap::new();
+        for i in indexes {
+            let symbol = &symbols[i];
+            let earlier = definitions.entry(&symbol.name).or_default();
+            let first = earlier.iter().find(|j| {
+                !(overloadable.contains(&i)
+                    && overloadable.contains(j)
+                    && symbols[**j].kind == symbol.kind)
+            });
+
+            if let Some(&j) = first {
+                table.duplicates.push((i, j));
+            }
+            earlier.push(i);
+        }
+    }
 
-            let scope_id = self.get_scope_id(node.range.start).unwrap();
-            let scope_ids: Vec<NodeId> = scope_id.predecessors(&self.arena).collect();
+    /// Definitions hiding a symbol of the same name from an enclosing scope.
+    /// Members, like the fields of a field scope, are skipped.
+    fn parse_shadowing(&mut self, language_def: &LanguageDefinition) {
+        self.shadowed_list.clear();
+        let Some(root_id) = self.root_id else {
+            return;
+        };
+
+        let mut names = ScopeNames::default();
+        for scope_id in root_id.descendants(&self.arena).skip(1) {
+            for symbol in &self.arena.get(scope_id).unwrap().get().symbols {
+                if language_def.is_member_symbol(&symbol.kind) {
+                    continue;
+                }
 
-            let mut found = false;
-            for id in scope_ids {
-                if let Some(index) = self
-                    .arena
-                    .get(id)
-                    .unwrap()
-                    .get()
-                    .symbols
-                    .iter()
-                    .position(|s| &s.name == symbol_name)
-                {
-                    let symbol = &mut self.arena.get_mut(id).unwrap().get_mut().symbols[index];
-                    node.link(id, index);
-                    found = true;
-                    symbol.usages.push(Usage::new_local(node.range));
-                    break;
+                let outer = scope_id.ancestors(&self.arena).skip(1).find_map(|id| {
+                    names
+                        .get(&self.arena, id, &symbol.name)
+                        .map(|index| &self.arena.get(id).unwrap().get().symbols[index])
+                });
+
+                if let Some(outer) = outer {
+                    self.shadowed_list.push((
+                        symbol.name.clone(),
+                        symbol.def_range,
+                        outer.def_range,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Paths of the symbols, the symbols of scopes that are not the field
+    /// scope of a symbol belong to the enclosing one.
+    fn parse_paths(&mut self) {
+        self.paths.clear();
+        self.symbol_paths.clear();
+        let Some(root_id) = self.root_id else {
+            return;
+        };
+
+        let mut symbols: Vec<(Vec<String>, Position, ScopeId, usize)> = vec![];
+        let mut queue: Vec<(ScopeId, Vec<String>)> = vec![(root_id, vec![])];
+        while let Some((scope_id, names)) = queue.pop() {
+            let scope = self.arena.get(scope_id).unwrap().get();
+
+            let mut owners: HashMap<ScopeId, &str> = HashMap::new();
+            for (index, symbol) in scope.symbols.iter().enumerate() {
+                let mut symbol_names = names.clone();
+                symbol_names.push(symbol.name.clone());
+                symbols.push((symbol_names, symbol.def_range.start, scope_id, index));
+
+                if let Some(field_scope_id) = symbol.field_scope_id {
+                    owners.insert(field_scope_id, &symbol.name);
                 }
             }
 
-            if !found {
-                self.undefined_list.push((node.content.clone(), node.range));
+            for child_scope_id in scope_id.children(&self.arena) {
+                let mut child_names = names.clone();
+                if let Some(owner) = owners.get(&child_scope_id) {
+                    child_names.push(owner.to_string());
+                }
+                queue.push((child_scope_id, child_names));
+            }
+        }
+
+        symbols.sort_by_key(|symbol| symbol.1);
+        let mut counts: HashMap<Vec<String>, usize> = HashMap::new();
+        for (names, _, scope_id, index) in symbols {
+            let count = counts.entry(names.clone()).or_default();
+            let path = SymbolPath {
+                names,
+                disambiguator: *count,
+            };
+            *count += 1;
+
+            self.paths.insert(path.clone(), (scope_id, index));
+            self.symbol_paths.insert((scope_id, index), path);
+        }
+    }
+
+    fn parse_names(&mut self) {
+        self.names.clear();
+        let Some(root_id) = self.root_id else {
+            return;
+        };
+
+        for scope_id in root_id.descendants(&self.arena) {
+            let symbols = &self.arena.get(scope_id).unwrap().get().symbols;
+            for (index, symbol) in symbols.iter().enumerate() {
+                self.names
+                    .push((symbol.name.clone(), SymbolId::new(None, scope_id, index)));
             }
         }
     }
 
-    fn parse_types(&mut self, root_id: NodeId, ast_arena: &mut Arena<Node>) {
-        for node_id in root_id.descendants(ast_arena) {
-            if let language_def::Symbol::Init {
-                kind,
-                name_node,
-                type_node: Some(type_node_query),
-            } = ast_arena.get(node_id).unwrap().get().symbol.clone()
-            {
-                if let Some(type_node_id) = node_id.children(ast_arena).find(|id| {
-                    ast_arena.get(*id).unwrap().get().kind
-                        == NodeKind::Node(type_node_query.clone())
-                }) {
-                    if let Some(symbol_id) = ast_arena
-                        .get(type_node_id)
-                        .unwrap()
-                        .get()
-                        .linked_symbol
-                        .clone()
-                    {
-                        let name_node_id = node_id
-                            .children(ast_arena)
-                            .find(|id| {
-                                ast_arena.get(*id).unwrap().get().kind
-                                    == NodeKind::Node(name_node.clone())
-                            })
-                            .unwrap();
-
-                        if let Some(name_symbol_id) = ast_arena
-                            .get(name_node_id)
-                            .unwrap()
-                            .get()
-                            .linked_symbol
-                            .clone()
-                        {
-                            self.get_symbol_mut(name_symbol_id).unwrap().type_symbol =
-                                Some(symbol_id);
-                        }
+    /// Links the usages outside of the kept scopes to the nearest definition
+    /// of their name, walking the AST with the scopes enclosing the current
+    /// node.
+    fn parse_usages(
+        &mut self,
+        root_id: NodeId,
+        arena: &mut Arena<Node>,
+        kept: &HashMap<NodeId, ScopeId>,
+    ) {
+        let mut names = ScopeNames::default();
+        let mut scopes: Vec<ScopeId> = vec![];
+        // Nodes left to visit, `None` leaving the last scope
+        let mut stack: Vec<Option<NodeId>> = vec![Some(root_id)];
+
+        while let Some(node_id) = stack.pop() {
+            let Some(node_id) = node_id else {
+                scopes.pop();
+                continue;
+            };
+            if kept.contains_key(&node_id) {
+                continue;
+            }
+
+            if let Some(scope_id) = self.scope_nodes.get(&node_id) {
+                scopes.push(*scope_id);
+                stack.push(None);
+            }
+            stack.extend(node_id.children(arena).rev().map(Some));
+
+            let scope_id = *scopes.last().unwrap();
+            let node = arena.get(node_id).unwrap().get();
+            match node.symbol {
+                language_def::Symbol::Usage => {
+                    let link = scopes.iter().rev().find_map(|scope_id| {
+                        names
+                            .get(&self.arena, *scope_id, &node.content)
+                            .map(|index| (*scope_id, index))
+                    });
+                    self.link_usage(arena, node_id, link);
+                    if link.map(|(link_scope_id, _)| link_scope_id) != Some(scope_id) {
+                        self.arena[scope_id].get_mut().outer_usages.push(node_id);
                     }
-                } else {
-                    error!("Failed to parse type of symbol {kind}. This is caused by a problem within the Lever rules file.");
                 }
+                language_def::Symbol::MemberUsage => {
+                    self.arena[scope_id].get_mut().member_usages.push(node_id);
+                }
+                _ => {}
             }
         }
     }
 
-    fn parse_member_usages(&mut self, root_id: NodeId, arena: &mut Arena<Node>) {
-        let ids: Vec<NodeId> = root_id
-            .descendants(arena)
-            .filter(|id| {
-                matches!(
-                    arena.get(*id).unwrap().get().symbol,
-                    language_def::Symbol::MemberUsage
-                )
-            })
+    /// Resolves again the usages of the kept scopes that were not linked to
+    /// a kept scope, their definitions may have changed.
+    fn relink_outer_usages(&mut self, arena: &mut Arena<Node>, kept: &HashMap<NodeId, ScopeId>) {
+        let kept_scopes: HashSet<ScopeId> = kept.values().copied().collect();
+        let mut names = ScopeNames::default();
+
+        for scope_id in &kept_scopes {
+            let outer_usages = std::mem::take(&mut self.arena[*scope_id].get_mut().outer_usages);
+
+            for node_id in &outer_usages {
+                let node = arena.get(*node_id).unwrap().get();
+                if node.linked_symbol.as_ref().is_some_and(|symbol_id| {
+                    symbol_id.file_id.is_none() && kept_scopes.contains(&symbol_id.symbol_table_id)
+                }) {
+                    continue;
+                }
+
+                let link = scope_id.ancestors(&self.arena).find_map(|ancestor_id| {
+                    names
+                        .get(&self.arena, ancestor_id, &node.content)
+                        .map(|index| (ancestor_id, index))
+                });
+                self.link_usage(arena, *node_id, link);
+            }
+
+            self.arena[*scope_id].get_mut().outer_usages = outer_usages;
+        }
+    }
+
+    fn link_usage(
+        &mut self,
+        arena: &mut Arena<Node>,
+        node_id: NodeId,
+        link: Option<(ScopeId, usize)>,
+    ) {
+        let node = arena.get_mut(node_id).unwrap().get_mut();
+        node.linked_symbol = None;
+
+        if let Some((scope_id, index)) = link {
+            node.link(scope_id, index);
+            self.arena[scope_id].get_mut().usage_nodes[index].push(node_id);
+        }
+    }
+
+    /// Sets the types of the symbols to the symbols their type nodes are
+    /// linked to.
+    fn parse_types(&mut self, arena: &Arena<Node>) {
+        let Some(root_id) = self.root_id else {
+            return;
+        };
+
+        let scope_ids: Vec<ScopeId> = root_id.descendants(&self.arena).collect();
+        for scope_id in scope_ids {
+            let table = self.arena[scope_id].get_mut();
+            for (index, type_node_id) in &table.typed_symbols {
+                table.symbols[*index].type_symbol = arena
+                    .get(*type_node_id)
+                    .unwrap()
+                    .get()
+                    .linked_symbol
+                    .clone();
+            }
+        }
+    }
+
+    /// Links member usages to the field scope of the type of their object.
+    /// They are all linked again, since the types may be defined anywhere in
+    /// the file.
+    fn parse_member_usages(&mut self, arena: &mut Arena<Node>) {
+        self.undefined_member_list.clear();
+        let Some(root_id) = self.root_id else {
+            return;
+        };
+
+        let mut ids: Vec<NodeId> = root_id
+            .descendants(&self.arena)
+            .flat_map(|scope_id| self.arena[scope_id].get().member_usages.clone())
             .collect();
+        // In the order of the file, so that the objects of chained members
+        // are linked first
+        ids.sort_by_key(|id| arena.get(*id).unwrap().get().range.start);
 
         for id in ids {
-            if let Some(previous_sibling_id) = arena.get(id).unwrap().previous_sibling() {
-                let previous_sibling = arena.get(previous_sibling_id).unwrap().get();
-                match previous_sibling.symbol {
-                    language_def::Symbol::Usage => {
-                        if let Some(symbol_id) = previous_sibling.linked_symbol.clone() {
-                            let parent_symbol = self.get_symbol(symbol_id).unwrap();
-
-                            if let Some(parent_type_symbol_id) = parent_symbol.type_symbol.clone() {
-                                let parent_type_symbol =
-                                    self.get_symbol(parent_type_symbol_id).unwrap();
-
-                                if let Some(field_scope_id) = parent_type_symbol.field_scope_id {
-                                    let scope_table =
-                                        self.arena.get_mut(field_scope_id).unwrap().get_mut();
-
-                                    if let Some(member_symbol_index) =
-                                        scope_table.symbols.iter().position(|s| {
-                                            s.name == arena.get(id).unwrap().get().content
-                                        })
-                                    {
-                                        arena
-                                            .get_mut(id)
-                                            .unwrap()
-                                            .get_mut()
-                                            .link(field_scope_id, member_symbol_index);
-                                        scope_table
-                                            .symbols
-                                            .get_mut(member_symbol_index)
-                                            .unwrap()
-                                            .usages
-                                            .push(Usage::new_local(
-                                                arena.get(id).unwrap().get().range,
-                                            ));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    language_def::Symbol::Expression => {
-                        if let Some(previous_symbol_id) =
-                            previous_sibling_id.children(arena).find(|id| {
-                                matches!(
-                                    arena.get(*id).unwrap().get().symbol,
-                                    language_def::Symbol::MemberUsage
-                                )
-                            })
-                        {
-                            if let Some(previous_sibling) = arena.get(previous_symbol_id) {
-                                if let Some(symbol_id) =
-                                    previous_sibling.get().linked_symbol.clone()
-                                {
-                                    let parent_symbol = self.get_symbol(symbol_id).unwrap();
-
-                                    if let Some(parent_type_symbol_id) =
-                                        parent_symbol.type_symbol.clone()
-                                    {
-                                        let parent_type_symbol =
-                                            self.get_symbol(parent_type_symbol_id).unwrap();
-
-                                        if let Some(field_scope_id) =
-                                            parent_type_symbol.field_scope_id
-                                        {
-                                            let scope_table = self
-                                                .arena
-                                                .get_mut(field_scope_id)
-                                                .unwrap()
-                                                .get_mut();
-
-                                            if let Some(member_symbol_index) =
-                                                scope_table.symbols.iter().position(|s| {
-                                                    s.name == arena.get(id).unwrap().get().content
-                                                })
-                                            {
-                                                arena
-                                                    .get_mut(id)
-                                                    .unwrap()
-                                                    .get_mut()
-                                                    .link(field_scope_id, member_symbol_index);
-                                                scope_table
-                                                    .symbols
-                                                    .get_mut(member_symbol_index)
-                                                    .unwrap()
-                                                    .usages
-                                                    .push(Usage::new_local(
-                                                        arena.get(id).unwrap().get().range,
-                                                    ));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
+            arena.get_mut(id).unwrap().get_mut().linked_symbol = None;
+            let Some(field_scope_id) = self.get_member_scope(arena, id) else {
+                continue;
+            };
+
+            let node = arena.get_mut(id).unwrap().get_mut();
+            let scope_table = self.arena[field_scope_id].get_mut();
+            if let Some(member_symbol_index) = scope_table
+                .symbols
+                .iter()
+                .position(|s| s.name == node.content)
+            {
+                node.link(field_scope_id, member_symbol_index);
+                scope_table.usage_nodes[member_symbol_index].push(id);
+            } else {
+                self.undefined_member_list
+                    .push((node.content.clone(), node.range));
+            }
+        }
+    }
+
+    /// Field scope of the type of the object of a member usage, which is the
+    /// usage or the member usage before it.
+    fn get_member_scope(&self, arena: &Arena<Node>, id: NodeId) -> Option<ScopeId> {
+        let object_id = arena.get(id)?.previous_sibling()?;
+        let object = arena.get(object_id)?.get();
+
+        let symbol_id = match object.symbol {
+            language_def::Symbol::Usage => object.linked_symbol.clone()?,
+            language_def::Symbol::Expression => {
+                let member_id = object_id.children(arena).find(|id| {
+                    matches!(
+                        arena.get(*id).unwrap().get().symbol,
+                        language_def::Symbol::MemberUsage
+                    )
+                })?;
+                arena.get(member_id)?.get().linked_symbol.clone()?
+            }
+            _ => return None,
+        };
+        let type_symbol_id = self.get_symbol(symbol_id)?.type_symbol.clone()?;
+
+        self.get_symbol(type_symbol_id)?.field_scope_id
+    }
+
+    /// Positions and usages of the symbols, read from the nodes they were
+    /// parsed from since the translation moves the kept nodes.
+    fn parse_positions(&mut self, arena: &Arena<Node>) {
+        self.undefined_list.clear();
+        self.duplicate_list.clear();
+        let Some(root_id) = self.root_id else {
+            return;
+        };
+
+        let scope_ids: Vec<ScopeId> = root_id.descendants(&self.arena).collect();
+        for scope_id in scope_ids {
+            let table = self.arena[scope_id].get_mut();
+            table.range = arena.get(table.node_id).unwrap().get().range;
+
+            for (index, symbol) in table.symbols.iter_mut().enumerate() {
+                symbol.def_range = arena.get(table.name_nodes[index]).unwrap().get().range;
+
+                // Usages linked elsewhere since they were recorded are dropped
+                let symbol_id = Some(SymbolId::new(None, scope_id, index));
+                let usage_nodes = &mut table.usage_nodes[index];
+                usage_nodes.sort_unstable();
+                usage_nodes.dedup();
+                usage_nodes.retain(|id| {
+                    !id.is_removed(arena)
+                        && arena.get(*id).unwrap().get().linked_symbol == symbol_id
+                });
+                usage_nodes.sort_by_key(|id| arena.get(*id).unwrap().get().range.start);
+
+                symbol.usages = usage_nodes
+                    .iter()
+                    .map(|id| Usage::new_local(arena.get(*id).unwrap().get().range))
+                    .collect();
+            }
+
+            for id in &table.outer_usages {
+                let node = arena.get(*id).unwrap().get();
+                if node.linked_symbol.is_none() {
+                    self.undefined_list.push((node.content.clone(), node.range));
                 }
             }
+            for (i, j) in &table.duplicates {
+                self.duplicate_list.push((
+                    table.symbols[*i].name.clone(),
+                    table.symbols[*i].def_range,
+                    table.symbols[*j].def_range,
+                ));
+            }
         }
+
+        self.undefined_list.sort_by_key(|(_, range)| range.start);
+        self.duplicate_list.sort_by_key(|(_, range, _)| range.start);
     }
 }
 
@@ -436,7 +794,7 @@ impl fmt::Display for SymbolTable {
         let mut output = String::new();
 
         let mut sorted = self.arena.iter().collect::<Vec<_>>();
-        sorted.sort_by(|a, b| a.get().range.start.cmp(&b.get().range.start));
+        sorted.sort_by_key(|node| node.get().range.start);
 
         for node in sorted {
             output.push_str(format!("{}\n", node.get()).as_str());
@@ -446,17 +804,71 @@ impl fmt::Display for SymbolTable {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+/// Index of the first symbol of each name in the scopes looked up so far.
+#[derive(Default)]
+struct ScopeNames {
+    scopes: HashMap<ScopeId, HashMap<String, usize>>,
+}
+
+impl ScopeNames {
+    fn get(
+        &mut self,
+        arena: &Arena<ScopeSymbolTable>,
+        scope_id: ScopeId,
+        name: &str,
+    ) -> Option<usize> {
+        self.scopes
+            .entry(scope_id)
+            .or_insert_with(|| {
+                let mut names = HashMap::new();
+                for (index, symbol) in arena
+                    .get(scope_id)
+                    .unwrap()
+                    .get()
+                    .symbols
+                    .iter()
+                    .enumerate()
+                {
+                    names.entry(symbol.name.clone()).or_insert(index);
+                }
+                names
+            })
+            .get(name)
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone)]
 struct ScopeSymbolTable {
     range: Range,
     symbols: Vec<Symbol>,
+    // AST nodes the scope and its symbols were parsed from, which are kept
+    // when the scope is
+    node_id: NodeId,
+    name_nodes: Vec<NodeId>,
+    // Usages linked to each symbol, some may have been linked elsewhere since
+    usage_nodes: Vec<Vec<NodeId>>,
+    // Usages of the scope not linked to its own symbols
+    outer_usages: Vec<NodeId>,
+    member_usages: Vec<NodeId>,
+    // Symbols with a type node
+    typed_symbols: Vec<(usize, NodeId)>,
+    // Redefinitions with the first definition of their name
+    duplicates: Vec<(usize, usize)>,
 }
 
 impl ScopeSymbolTable {
-    fn new(range: Range) -> ScopeSymbolTable {
+    fn new(node_id: NodeId, range: Range) -> ScopeSymbolTable {
         ScopeSymbolTable {
             range,
-            ..Default::default()
+            symbols: vec![],
+            node_id,
+            name_nodes: vec![],
+            usage_nodes: vec![],
+            outer_usages: vec![],
+            member_usages: vec![],
+            typed_symbols: vec![],
+            duplicates: vec![],
         }
     }
 }
@@ -483,3 +895,183 @@ impl fmt::Display for ScopeSymbolTable {
         fmt.write_str(&output)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+    use std::sync::Arc;
+
+    use super::{Actions, SymbolTable};
+    use crate::language_def::LanguageDefinition;
+    use crate::project::metadata::{Ast, RulesTranslator, SymbolId, Translator, Visitable};
+
+    const RULES: &str = include_str!("../../../../../tests/fixtures/javascript.ron");
+
+    const SOURCE: &str = "class Point {
+    x = 1;
+    y = 2;
+}
+
+function area() {
+    const p = new Point();
+    p.x;
+    let total = p.y;
+    total;
+}
+
+function scale() {
+    const p = new Point();
+    p.x.y;
+    count;
+    area();
+}
+
+const shared = new Point();
+shared.x;
+area();
+";
+
+    // Replaced text and its replacement, applied in order
+    const EDITS: &[(&str, &str)] = &[
+        ("let total", "let sum"),
+        ("count;", "counts;"),
+        ("const shared", "let counts = 1;\nconst shared"),
+        ("class Point", "class Shape"),
+        ("class Shape", "class Point"),
+        ("class Point", "\n\nclass Point"),
+        ("    y = 2;\n", "    y = 2;\n    z = 3;\n"),
+        ("    total;\n}", "    total;\n"),
+        ("    total;\n", "    total;\n}"),
+        ("function scale", "function resize"),
+    ];
+
+    /// Replaces the first occurrence of `pattern`, returning the edit.
+    fn replace(source_code: &mut String, pattern: &str, text: &str) -> tree_sitter::InputEdit {
+        let point = |source_code: &str, offset: usize| {
+            let row = source_code[..offset].matches('\n').count();
+            let column = offset - source_code[..offset].rfind('\n').map_or(0, |i| i + 1);
+            tree_sitter::Point::new(row, column)
+        };
+
+        let start_byte = source_code.find(pattern).unwrap();
+        let old_end_byte = start_byte + pattern.len();
+        let start_position = point(source_code, start_byte);
+        let old_end_position = point(source_code, old_end_byte);
+        source_code.replace_range(start_byte..old_end_byte, text);
+
+        tree_sitter::InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte: start_byte + text.len(),
+            start_position,
+            old_end_position,
+            new_end_position: point(source_code, start_byte + text.len()),
+        }
+    }
+
+    /// Scopes, symbols, links and problems of a table, without its ids.
+    fn dump(table: &SymbolTable, ast: &Ast) -> String {
+        let range = |symbol_id: &SymbolId| {
+            table
+                .get_symbol(symbol_id.clone())
+                .map(|symbol| symbol.def_range)
+        };
+        let mut output = String::new();
+
+        for scope_id in table.root_id.unwrap().descendants(&table.arena) {
+            let scope = table.arena[scope_id].get();
+            writeln!(output, "scope {:?}", scope.range).unwrap();
+            for symbol in &scope.symbols {
+                writeln!(
+                    output,
+                    "  {} {} {:?} type: {:?} fields: {:?} usages: {:?}",
+                    symbol.kind,
+                    symbol.name,
+                    symbol.def_range,
+                    symbol.type_symbol.as_ref().map(range),
+                    symbol
+                        .field_scope_id
+                        .map(|scope_id| table.arena[scope_id].get().range),
+                    symbol
+                        .usages
+                        .iter()
+                        .map(|usage| usage.range)
+                        .collect::<Vec<_>>(),
+                )
+                .unwrap();
+            }
+        }
+
+        for node in ast.visit_root().get_descendants() {
+            if let Some(symbol_id) = &node.get().linked_symbol {
+                writeln!(output, "link {:?} {:?}", node.get().range, range(symbol_id)).unwrap();
+            }
+        }
+
+        let mut paths: Vec<_> = table
+            .paths
+            .iter()
+            .map(|(path, (scope_id, index))| {
+                let symbol = &table.arena[*scope_id].get().symbols[*index];
+                (path.names.join("."), path.disambiguator, symbol.def_range)
+            })
+            .collect();
+        paths.sort_by_key(|(_, _, range)| range.start);
+        let names: Vec<_> = table
+            .names
+            .iter()
+            .map(|(name, symbol_id)| (name, range(symbol_id)))
+            .collect();
+
+        writeln!(output, "undefined: {:?}", table.undefined_list).unwrap();
+        writeln!(
+            output,
+            "undefined members: {:?}",
+            table.undefined_member_list
+        )
+        .unwrap();
+        writeln!(output, "duplicates: {:?}", table.duplicate_list).unwrap();
+        writeln!(output, "shadowed: {:?}", table.shadowed_list).unwrap();
+        writeln!(output, "paths: {paths:?}").unwrap();
+        writeln!(output, "names: {names:?}").unwrap();
+
+        output
+    }
+
+    #[test]
+    fn test_update_matches_new() {
+        let language = tree_sitter_javascript::language();
+        let language_def = Arc::new(LanguageDefinition::load(RULES, language).unwrap().0);
+        let translator = RulesTranslator::new(language_def.clone());
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(language).unwrap();
+
+        let mut source_code = SOURCE.to_string();
+        let mut tree = parser.parse(&source_code, None).unwrap();
+        let mut ast = translator.translate(&source_code, tree.clone());
+        let mut table = SymbolTable::new(&mut ast, &language_def);
+
+        for (pattern, text) in EDITS {
+            let edit = replace(&mut source_code, pattern, text);
+            ast.edit(&edit);
+            tree.edit(&edit);
+            tree = parser.parse(&source_code, Some(&tree)).unwrap();
+
+            translator.retranslate(&source_code, tree.clone(), &mut ast);
+            table.update(&mut ast, &language_def);
+            // Scopes of the functions that were not edited are kept
+            assert!(table
+                .scope_nodes
+                .keys()
+                .any(|node_id| ast.is_reused(*node_id)));
+
+            let mut expected_ast = translator.translate(&source_code, tree.clone());
+            let expected = SymbolTable::new(&mut expected_ast, &language_def);
+            assert_eq!(
+                dump(&table, &ast),
+                dump(&expected, &expected_ast),
+                "\nAfter replacing {pattern:?}:\n{source_code}"
+            );
+        }
+    }
+}
@@ -4,6 +4,8 @@ mod features;
 mod file;
 mod metadata;
 
+pub use file::File;
 pub use metadata::{
-    Ast, AstQuery, Node, NodeKind, SymbolTableQuery, Translator, VisitNode, Visitable,
+    Ast, AstQuery, Node, NodeKind, ReusableNodes, RulesTranslator, StableSymbolId, SymbolPath,
+    SymbolTableQuery, Translator, VisitNode, Visitable,
 };
@@ -1,30 +1,76 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
+use indextree::{Arena, NodeId};
 use petgraph::visit::EdgeRef;
 use petgraph::EdgeDirection;
-use petgraph::{dot::Dot, prelude::NodeIndex, Graph};
+use petgraph::{dot::Dot, prelude::NodeIndex, stable_graph::StableGraph};
 use serde_json::Value;
 use tower_lsp::lsp_types::{
-    CompletionContext, CompletionItem, CompletionTriggerKind, Diagnostic, HoverContents, Location,
-    MarkedString, Position, Range, SemanticTokensResult, TextDocumentContentChangeEvent, TextEdit,
+    CodeLens, Command, CompletionContext, CompletionItem, CompletionTriggerKind, Diagnostic,
+    DocumentSymbol, FileChangeType, HoverContents, Location, MarkedString, Position, Range,
+    SemanticTokensResult, SymbolInformation, SymbolKind, TextDocumentContentChangeEvent, TextEdit,
     Url, WorkspaceEdit,
 };
 
-use super::metadata::{AstEditor, AstQuery, SymbolId, SymbolTableQuery, Usage, Visitable};
+use super::metadata::{
+    AstEditor, AstQuery, Node, NodeKind, StableSymbolId, Symbol, SymbolId, SymbolTableEditor,
+    SymbolTableQuery, Usage, Visitable,
+};
+use crate::language_def::{self, LanguageRegistry};
+use crate::lsp_mappings::HighlightType;
 use crate::settings::Settings;
+use crate::utils;
 
 use super::file::File;
 
 pub trait FileManagement {
     fn get_file(&self, url: &Url) -> Option<&File>;
     fn get_file_mut(&mut self, url: &Url) -> Option<&mut File>;
-    fn add_file(&mut self, url: Url, content: &str);
-    fn update_file(&mut self, url: &Url, changes: Vec<TextDocumentContentChangeEvent>);
+    /// Adds a file opened in the editor, or replaces the content read from
+    /// disk with the one of the editor. Returns the urls of the files
+    /// importing it which were linked again.
+    fn open_file(&mut self, url: Url, content: &str, version: i32) -> Vec<Url>;
+    /// Reloads a closed file from disk if an open file still imports it,
+    /// removes the files no open file imports anymore. Returns the urls of
+    /// the removed files and of the files that need fresh diagnostics.
+    fn close_file(&mut self, url: &Url) -> (Vec<Url>, Vec<Url>);
+    /// Applies a change of a file on disk, unless the file is open. Returns
+    /// the urls of the removed files and of the files that need fresh
+    /// diagnostics.
+    fn apply_disk_change(&mut self, url: &Url, change: FileChangeType) -> (Vec<Url>, Vec<Url>);
+    /// Returns the urls of the files importing the updated one, which were
+    /// linked again and need fresh diagnostics. `version` is the one of the
+    /// editor buffer, `None` when the changes come from disk.
+    fn update_file(
+        &mut self,
+        url: &Url,
+        version: Option<i32>,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Vec<Url>;
+    fn rebuild_files(&mut self);
+    fn get_urls(&self) -> Vec<Url>;
+    /// Records that the buffer of an open file was saved.
+    fn mark_saved(&mut self, url: &Url);
+    /// Open files with changes that are not saved on disk.
+    fn get_dirty_files(&self) -> Vec<&File>;
 }
 
 pub trait LanguageActions {
     fn get_definition_location(&self, url: &Url, symbol_position: Position) -> Option<Location>;
     fn get_semantic_tokens(&self, url: &Url) -> Option<SemanticTokensResult>;
+    fn get_document_symbols(&self, url: &Url) -> Option<Vec<DocumentSymbol>>;
+    fn get_workspace_symbols(&self, query: &str) -> Vec<SymbolInformation>;
+    fn get_references(
+        &self,
+        url: &Url,
+        symbol_position: Position,
+        include_declaration: bool,
+    ) -> Option<Vec<Location>>;
     fn rename_symbol(
         &mut self,
         url: &Url,
@@ -38,6 +84,10 @@ pub trait LanguageActions {
         context: Option<CompletionContext>,
     ) -> Option<Vec<CompletionItem>>;
     fn get_hover_info(&self, url: &Url, position: Position) -> Option<HoverContents>;
+    /// Lenses on the top-level symbols of a file, resolved to their number
+    /// of references by `resolve_code_lens`.
+    fn get_code_lenses(&self, url: &Url) -> Vec<CodeLens>;
+    fn resolve_code_lens(&self, code_lens: CodeLens) -> CodeLens;
     fn get_quick_diagnostics(&self, url: &Url) -> Vec<Diagnostic>;
     fn get_full_diagnostics(&self, url: &Url) -> Vec<Diagnostic>;
 }
@@ -48,32 +98,225 @@ pub enum Import {
     Library,
 }
 
+/// Edge of the file graph, from the importing file to the imported one.
+#[derive(Debug, Clone)]
+pub struct ImportEdge {
+    pub import: Import,
+    /// Range of the imported path in the importing file
+    pub range: Range,
+}
+
+/// Resolved import of a file, or the range of an import that is not found
+/// with the paths it was searched at.
+pub type ImportPath = Result<(ImportEdge, PathBuf), (Range, Vec<PathBuf>)>;
+
+// Keeps workspace symbol responses small, clients filter them again anyway
+const MAX_WORKSPACE_SYMBOLS: usize = 128;
+
+/// A folder of the workspace.
+struct Root {
+    path: PathBuf,
+    // Received from the client, `None` until then
+    settings: Option<Settings>,
+}
+
+impl Root {
+    fn new(path: PathBuf) -> Root {
+        Root {
+            path,
+            settings: None,
+        }
+    }
+}
+
 pub struct Workspace {
-    root_path: Option<PathBuf>,
+    // Folders of the workspace, their files are indexed
+    roots: Vec<Root>,
+    // Used for the files outside the roots and the roots without settings
     settings: Settings,
     url_node_map: HashMap<Url, NodeIndex>,
-    file_graph: Graph<File, Import>,
-    tree_sitter_language: tree_sitter::Language,
+    // Removing a file keeps the indexes of the other files
+    file_graph: StableGraph<File, ImportEdge>,
+    // Files with a buffer in the editor, the others are read from disk
+    open_files: HashSet<NodeIndex>,
+    // Files found in the roots, kept even when no open file imports them
+    workspace_files: HashSet<NodeIndex>,
+    // Files of the import cycle of each file, found again once the file
+    // graph changed
+    import_cycles: Mutex<Option<HashMap<NodeIndex, Arc<[NodeIndex]>>>>,
+    // Files whose imports were searched at a path that does not exist or
+    // could not be read, to link them again once it is created or changed
+    unresolved_imports: HashMap<Url, HashSet<NodeIndex>>,
+    registry: Arc<LanguageRegistry>,
 }
 
 impl Workspace {
-    pub fn new(tree_sitter_language: tree_sitter::Language) -> Workspace {
+    pub fn new(registry: Arc<LanguageRegistry>) -> Workspace {
         Workspace {
-            root_path: None,
+            roots: vec![],
             settings: Settings::default(),
             url_node_map: HashMap::new(),
-            file_graph: Graph::new(),
-            tree_sitter_language,
+            file_graph: StableGraph::new(),
+            open_files: HashSet::new(),
+            workspace_files: HashSet::new(),
+            import_cycles: Mutex::new(None),
+            unresolved_imports: HashMap::new(),
+            registry,
+        }
+    }
+
+    pub fn set_roots(&mut self, roots: Vec<PathBuf>) {
+        self.roots = roots.into_iter().map(Root::new).collect();
+    }
+
+    pub fn get_roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|root| root.path.clone()).collect()
+    }
+
+    /// Adds a folder to the workspace, its files are not indexed yet.
+    pub fn add_root(&mut self, path: PathBuf) {
+        if !self.roots.iter().any(|root| root.path == path) {
+            self.roots.push(Root::new(path));
+        }
+    }
+
+    /// Removes a folder from the workspace and the files only it kept.
+    /// Returns their urls.
+    pub fn remove_root(&mut self, path: &Path) -> Vec<Url> {
+        self.roots.retain(|root| root.path != path);
+
+        let dropped: Vec<NodeIndex> = self
+            .workspace_files
+            .iter()
+            .copied()
+            .filter(|file_index| {
+                let url = &self.file_graph.node_weight(*file_index).unwrap().uri;
+                !self.is_workspace_file(url)
+            })
+            .collect();
+        for file_index in dropped {
+            self.workspace_files.remove(&file_index);
+        }
+
+        self.remove_unused_files()
+    }
+
+    /// Settings of a folder, they replace the workspace settings for its
+    /// files.
+    pub fn update_root_settings(&mut self, path: &Path, settings: Value) {
+        let Some(root) = self.roots.iter_mut().find(|root| root.path == path) else {
+            return;
+        };
+        let settings = Settings::parse(settings);
+        info!("Settings of {}: {:?}", path.display(), settings);
+
+        let library_paths_changed = root
+            .settings
+            .as_ref()
+            .map_or(&self.settings, |settings| settings)
+            .library_paths
+            != settings.library_paths;
+        root.settings = Some(settings);
+
+        if library_paths_changed {
+            self.relink_files(|workspace, url| {
+                workspace
+                    .get_root(url)
+                    .is_some_and(|root| root.path == path)
+            });
         }
     }
 
-    pub fn set_root_path(&mut self, path: Option<PathBuf>) {
-        self.root_path = path;
+    /// The deepest root containing the file.
+    fn get_root(&self, url: &Url) -> Option<&Root> {
+        let path = url.to_file_path().ok()?;
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.components().count())
+    }
+
+    fn get_settings(&self, url: &Url) -> &Settings {
+        self.get_root(url)
+            .and_then(|root| root.settings.as_ref())
+            .unwrap_or(&self.settings)
+    }
+
+    /// Directories of the settings searched for the library imports of the
+    /// file.
+    fn get_library_directories(&self, url: &Url) -> Vec<PathBuf> {
+        let library_paths = &self.get_settings(url).library_paths;
+        match self.get_root(url) {
+            Some(root) => library_paths
+                .iter()
+                .map(|path| root.path.join(path))
+                .collect(),
+            None => library_paths.clone(),
+        }
+    }
+
+    /// Whether the file is in a root and has the extension of a language.
+    fn is_workspace_file(&self, url: &Url) -> bool {
+        url.to_file_path().is_ok_and(|path| {
+            self.registry.matches_path(&path)
+                && self.roots.iter().any(|root| path.starts_with(&root.path))
+        })
+    }
+
+    /// Adds the files found in the folders of the workspace, the files the
+    /// workspace already has are kept. Files may be added in several calls,
+    /// the imported files that are not added yet are read from disk.
+    pub fn add_workspace_files(&mut self, files: Vec<File>) {
+        let mut new_files: Vec<NodeIndex> = vec![];
+
+        for file in files {
+            let file_index = match self.url_node_map.get(&file.uri) {
+                Some(file_index) => *file_index,
+                None => {
+                    let url = file.uri.clone();
+                    let file_index = self.file_graph.add_node(file);
+                    self.url_node_map.insert(url, file_index);
+                    new_files.push(file_index);
+                    file_index
+                }
+            };
+            self.workspace_files.insert(file_index);
+        }
+
+        // Once every file is added, since they may import each other
+        for file_index in new_files {
+            self.link_imports(file_index);
+        }
     }
 
     pub fn update_settings(&mut self, settings: Value) {
-        self.settings = Settings::parse(settings);
-        info!("Settings: {:?}", self.settings);
+        let settings = Settings::parse(settings);
+        info!("Settings: {:?}", settings);
+
+        let library_paths_changed = self.settings.library_paths != settings.library_paths;
+        self.settings = settings;
+
+        if library_paths_changed {
+            self.relink_files(|workspace, url| {
+                workspace
+                    .get_root(url)
+                    .is_none_or(|root| root.settings.is_none())
+            });
+        }
+    }
+
+    /// Resolves again the imports of the files accepted by `filter`.
+    fn relink_files(&mut self, filter: impl Fn(&Workspace, &Url) -> bool) {
+        let files: Vec<NodeIndex> = self
+            .file_graph
+            .node_indices()
+            .filter(|file_index| filter(self, &self.file_graph[*file_index].uri))
+            .collect();
+
+        for file_index in files {
+            self.relink_imports(file_index);
+        }
+        self.remove_unused_files();
     }
 
     fn add_file(&mut self, url: &Url, content: &str) -> Option<NodeIndex> {
@@ -81,54 +324,89 @@ impl Workspace {
             return None;
         }
 
-        let file = File::new(url.clone(), content, self.tree_sitter_language);
-
-        let import_paths = file.get_import_paths();
-        debug!("Resolved import paths: {:?}", import_paths);
+        let language = self.registry.get_for_url(url)?;
+        let file = File::new(url.clone(), content, &language);
 
         let new_file_index = self.file_graph.add_node(file);
         self.url_node_map.insert(url.clone(), new_file_index);
 
+        self.link_imports(new_file_index);
+
+        Some(new_file_index)
+    }
+
+    fn link_imports(&mut self, file_index: NodeIndex) {
+        self.invalidate_import_cycles();
+        self.remove_unresolved_imports(|importing_file| importing_file == file_index);
+        let file = self.file_graph.node_weight(file_index).unwrap();
+        let url = file.uri.clone();
+
+        let import_paths = file.get_import_paths(&self.get_library_directories(&url));
+        debug!("Resolved import paths: {:?}", import_paths);
+
         for path in import_paths {
             match path {
-                Ok((import_type, path)) => {
+                Ok((import_edge, path)) => {
                     let imported_file_url = Url::from_file_path(path.clone()).unwrap();
 
                     let maybe_imported_file_index = if let Some(imported_file_index) =
                         self.url_node_map.get(&imported_file_url)
                     {
                         self.file_graph
-                            .add_edge(new_file_index, *imported_file_index, import_type);
+                            .add_edge(file_index, *imported_file_index, import_edge);
                         Some(*imported_file_index)
                     } else {
-                        let content = fs::read_to_string(path).unwrap();
+                        let content = match fs::read_to_string(&path) {
+                            Ok(content) => content,
+                            Err(err) => {
+                                warn!("Failed to read {}: {}", path.display(), err);
+                                // Linked again once the file changes on disk
+                                self.unresolved_imports
+                                    .entry(imported_file_url)
+                                    .or_default()
+                                    .insert(file_index);
+                                add_import_error(
+                                    &url,
+                                    import_edge.range,
+                                    format!("File could not be read: {err}"),
+                                );
+                                continue;
+                            }
+                        };
                         let imported_file_index = self.add_file(&imported_file_url, &content);
                         if let Some(i) = imported_file_index {
-                            self.file_graph.add_edge(new_file_index, i, import_type);
-                            Some(i)
+                            self.file_graph.add_edge(file_index, i, import_edge);
                         } else {
-                            None
+                            add_import_error(
+                                &url,
+                                import_edge.range,
+                                String::from("No language is registered for this file."),
+                            );
                         }
+                        imported_file_index
                     };
 
                     if let Some(imported_file_index) = maybe_imported_file_index {
-                        self.link_imported_symbols(new_file_index, imported_file_index);
+                        self.link_imported_symbols(file_index, imported_file_index);
                     }
                 }
-                Err(range) => {
+                Err((range, paths)) => {
                     info!("Import problem");
 
-                    super::features::diagnostics::ImportErrors::add_error(
-                        url.clone(),
-                        Diagnostic::new_simple(range, String::from("File could not be found.")),
-                    );
+                    for path in paths {
+                        if let Ok(path_url) = Url::from_file_path(path) {
+                            self.unresolved_imports
+                                .entry(path_url)
+                                .or_default()
+                                .insert(file_index);
+                        }
+                    }
+                    add_import_error(&url, range, String::from("File could not be found."));
                 }
             }
         }
 
         debug!("File graph:\n{:?}", Dot::with_config(&self.file_graph, &[]));
-
-        Some(new_file_index)
     }
 
     fn link_imported_symbols(&mut self, file_index: NodeIndex, imported_file_index: NodeIndex) {
@@ -140,53 +418,712 @@ impl Workspace {
             .get_symbols_at_root();
 
         let file = self.file_graph.node_weight(file_index).unwrap();
-        let unlinked_symbols: HashMap<String, Range> = file
+        let unlinked_symbols = file
+            .symbol_table_manager
+            .lock()
+            .unwrap()
+            .get_unlinked_symbols();
+
+        let mut ast = file.ast_manager.lock().unwrap();
+        for (name, range) in unlinked_symbols {
+            let Some(i) = imported_symbols.iter().position(|s| s.name == name) else {
+                continue;
+            };
+            // Already linked to a symbol of another imported file
+            if ast
+                .visit_root()
+                .get_node_at_position(range.start)
+                .is_some_and(|node| node.get().linked_symbol.is_some())
+            {
+                continue;
+            }
+
+            let symbol_id = SymbolId::new(Some(imported_file_index), scope_id, i);
+            ast.link_symbol(symbol_id.clone(), range);
+
+            let mut imported_st = imported_file.symbol_table_manager.lock().unwrap();
+            let symbol = imported_st.get_symbol_mut(symbol_id).unwrap();
+            symbol.add_usage(Usage::new_external(file_index, range));
+        }
+        drop(ast);
+
+        self.link_imported_types(file_index, imported_file_index);
+        self.link_imported_members(file_index);
+    }
+
+    /// Sets the types of the symbols of a file that are defined in an
+    /// imported file, the symbol table only knows the types of the file.
+    fn link_imported_types(&self, file_index: NodeIndex, imported_file_index: NodeIndex) {
+        let file = self.file_graph.node_weight(file_index).unwrap();
+        let ast = file.ast_manager.lock().unwrap();
+        let mut st = file.symbol_table_manager.lock().unwrap();
+
+        for node in ast.visit_root().get_descendants() {
+            let language_def::Symbol::Init {
+                name_node,
+                type_node: Some(type_node),
+                ..
+            } = &node.get().symbol
+            else {
+                continue;
+            };
+            let linked_child = |kind: &str| {
+                node.get_child_of_kind(NodeKind::Node(kind.to_string()))?
+                    .get()
+                    .linked_symbol
+                    .clone()
+            };
+
+            let (Some(symbol_id), Some(type_id)) =
+                (linked_child(name_node), linked_child(type_node))
+            else {
+                continue;
+            };
+            if symbol_id.get_file_id().is_none()
+                && type_id.get_file_id() == Some(imported_file_index)
+            {
+                if let Some(symbol) = st.get_symbol_mut(symbol_id) {
+                    symbol.type_symbol = Some(type_id);
+                }
+            }
+        }
+    }
+
+    /// Links the member usages of a file whose object has a type defined in
+    /// another file, e.g. `p.x` with the class of `p` imported. Members of
+    /// the types of the file are linked by its symbol table.
+    fn link_imported_members(&self, file_index: NodeIndex) {
+        let file = self.file_graph.node_weight(file_index).unwrap();
+        let mut ast = file.ast_manager.lock().unwrap();
+        let root_id = ast.visit_root().get_id();
+        let arena = ast.get_ast().get_arena();
+
+        let members: Vec<NodeId> = root_id
+            .descendants(arena)
+            .filter(|id| {
+                let node = arena.get(*id).unwrap().get();
+                matches!(node.symbol, language_def::Symbol::MemberUsage)
+                    && node.linked_symbol.is_none()
+            })
+            .collect();
+
+        // In the order of the file, so that the objects of chained members
+        // are linked first
+        for id in members {
+            let Some(object_id) = get_member_object(arena, id) else {
+                continue;
+            };
+            let node = arena.get(id).unwrap().get();
+            let Some(member_id) = self.resolve_member(file_index, object_id, &node.content) else {
+                continue;
+            };
+
+            let range = node.range;
+            let member_file = self
+                .file_graph
+                .node_weight(member_id.get_file_id().unwrap())
+                .unwrap();
+            if let Some(member) = member_file
+                .symbol_table_manager
+                .lock()
+                .unwrap()
+                .get_symbol_mut(member_id.clone())
+            {
+                member.add_usage(Usage::new_external(file_index, range));
+            }
+            arena.get_mut(id).unwrap().get_mut().linked_symbol = Some(member_id);
+        }
+    }
+
+    /// Member of the type of a symbol linked from a file, when the type is
+    /// defined in another file.
+    fn resolve_member(
+        &self,
+        file_index: NodeIndex,
+        object_id: SymbolId,
+        name: &str,
+    ) -> Option<SymbolId> {
+        let object_file = object_id.get_file_id().unwrap_or(file_index);
+        let type_id = self
+            .file_graph
+            .node_weight(object_file)?
             .symbol_table_manager
             .lock()
             .unwrap()
-            .get_unlinked_symbols()
+            .get_symbol(object_id)?
+            .type_symbol
+            .clone()?;
+
+        let type_file = type_id.get_file_id().unwrap_or(object_file);
+        if type_file == file_index {
+            return None;
+        }
+
+        let st = self
+            .file_graph
+            .node_weight(type_file)?
+            .symbol_table_manager
+            .lock()
+            .unwrap();
+        let field_scope_id = st.get_symbol(type_id)?.field_scope_id?;
+        let index = st
+            .get_symbols_in_scope(field_scope_id)
+            .iter()
+            .position(|symbol| symbol.name == name)?;
+
+        Some(SymbolId::new(Some(type_file), field_scope_id, index))
+    }
+
+    /// Removes the links of a file to the symbols of an imported file.
+    fn unlink_file(&self, file_index: NodeIndex, imported_file_index: NodeIndex) {
+        let file = self.file_graph.node_weight(file_index).unwrap();
+        file.ast_manager
+            .lock()
+            .unwrap()
+            .unlink_file(imported_file_index);
+        file.symbol_table_manager
+            .lock()
+            .unwrap()
+            .remove_external_types(imported_file_index);
+    }
+
+    /// Stable identity of a symbol linked from the file `url`.
+    pub fn get_stable_symbol_id(&self, url: &Url, symbol_id: &SymbolId) -> Option<StableSymbolId> {
+        let file = match symbol_id.get_file_id() {
+            Some(file_index) => self.file_graph.node_weight(file_index)?,
+            None => self.get_file(url)?,
+        };
+        let path = file
+            .symbol_table_manager
+            .lock()
+            .unwrap()
+            .get_symbol_path(symbol_id)?;
+
+        Some(StableSymbolId {
+            uri: file.uri.clone(),
+            path,
+        })
+    }
+
+    /// Current id of a symbol, with the index of its file.
+    pub fn resolve_stable_symbol_id(&self, id: &StableSymbolId) -> Option<(NodeIndex, SymbolId)> {
+        let file_index = *self.url_node_map.get(&id.uri)?;
+        let mut symbol_id = self
+            .get_file(&id.uri)?
+            .symbol_table_manager
+            .lock()
+            .unwrap()
+            .get_symbol_id(&id.path)?;
+        symbol_id.file_id = Some(file_index);
+
+        Some((file_index, symbol_id))
+    }
+
+    /// Links of the files importing a file to its symbols, as stable ids to
+    /// link them again once it is rebuilt.
+    fn get_dependent_links(
+        &self,
+        file_index: NodeIndex,
+    ) -> Vec<(NodeIndex, Range, StableSymbolId)> {
+        let mut links = vec![];
+
+        for dependent in self
+            .file_graph
+            .neighbors_directed(file_index, EdgeDirection::Incoming)
+            .filter(|dependent| *dependent != file_index)
+        {
+            let file = self.file_graph.node_weight(dependent).unwrap();
+            let ast = file.ast_manager.lock().unwrap();
+
+            for node in ast.visit_root().get_descendants() {
+                let Some(symbol_id) = node.get().linked_symbol.as_ref() else {
+                    continue;
+                };
+                if symbol_id.get_file_id() != Some(file_index) {
+                    continue;
+                }
+
+                if let Some(id) = self.get_stable_symbol_id(&file.uri, symbol_id) {
+                    links.push((dependent, node.get().range, id));
+                }
+            }
+        }
+
+        links
+    }
+
+    /// Links the files importing a file again, since their links point into
+    /// its rebuilt symbol table. Links to symbols that still exist are kept,
+    /// the other usages are linked by name. Returns their urls.
+    fn relink_dependents(
+        &mut self,
+        file_index: NodeIndex,
+        links: Vec<(NodeIndex, Range, StableSymbolId)>,
+    ) -> Vec<Url> {
+        let mut dependents: Vec<NodeIndex> = self
+            .file_graph
+            .neighbors_directed(file_index, EdgeDirection::Incoming)
+            .filter(|dependent| *dependent != file_index)
+            .collect();
+        dependents.sort();
+        dependents.dedup();
+
+        for dependent in &dependents {
+            self.unlink_file(*dependent, file_index);
+        }
+
+        self.restore_links(links);
+        for dependent in &dependents {
+            self.link_imported_symbols(*dependent, file_index);
+        }
+
+        dependents
             .into_iter()
+            .map(|dependent| self.file_graph.node_weight(dependent).unwrap().uri.clone())
+            .collect()
+    }
+
+    /// Links usages to the symbols saved by `get_dependent_links` again, if
+    /// the symbols still exist and the usages are still unlinked.
+    fn restore_links(&self, links: Vec<(NodeIndex, Range, StableSymbolId)>) {
+        let mut unlinked: HashMap<NodeIndex, Vec<Range>> = HashMap::new();
+
+        for (dependent, range, id) in links {
+            let Some((imported_file_index, symbol_id)) = self.resolve_stable_symbol_id(&id) else {
+                continue;
+            };
+            let file = self.file_graph.node_weight(dependent).unwrap();
+            let is_unlinked = unlinked
+                .entry(dependent)
+                .or_insert_with(|| {
+                    file.symbol_table_manager
+                        .lock()
+                        .unwrap()
+                        .get_unlinked_symbols()
+                        .into_iter()
+                        .map(|(_, range)| range)
+                        .collect()
+                })
+                .contains(&range);
+            if !is_unlinked {
+                continue;
+            }
+
+            let imported_file = self.file_graph.node_weight(imported_file_index).unwrap();
+            file.ast_manager
+                .lock()
+                .unwrap()
+                .link_symbol(symbol_id.clone(), range);
+            if let Some(symbol) = imported_file
+                .symbol_table_manager
+                .lock()
+                .unwrap()
+                .get_symbol_mut(symbol_id)
+            {
+                symbol.add_usage(Usage::new_external(dependent, range));
+            }
+        }
+    }
+
+    /// Diagnostics that depend on the settings or on other files.
+    fn get_workspace_diagnostics(&self, file: &File) -> Vec<Diagnostic> {
+        let file_index = self.url_node_map[&file.uri];
+
+        let settings = self.get_settings(&file.uri);
+        let imported_symbols: Vec<(Url, Symbol)> = if settings.warn_import_shadowing {
+            self.file_graph
+                .neighbors_directed(file_index, EdgeDirection::Outgoing)
+                .flat_map(|index| {
+                    let imported_file = self.file_graph.node_weight(index).unwrap();
+                    let (symbols, _) = imported_file
+                        .symbol_table_manager
+                        .lock()
+                        .unwrap()
+                        .get_symbols_at_root();
+
+                    symbols
+                        .into_iter()
+                        .map(|symbol| (imported_file.uri.clone(), symbol))
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let mut diagnostics = super::features::diagnostics::Shadowing::get_diagnostics(
+            &file.uri,
+            &file.symbol_table_manager,
+            &imported_symbols,
+            &file.language_def,
+            settings,
+        );
+        diagnostics.append(&mut super::features::diagnostics::Unused::get_diagnostics(
+            &file.symbol_table_manager,
+            &file.language_def,
+            &self.get_unused_imports(file_index),
+        ));
+        diagnostics.append(
+            &mut super::features::diagnostics::ImportCycles::get_diagnostics(
+                self.get_import_cycles(file_index),
+                file.language_def.language.forbid_cyclic_imports,
+            ),
+        );
+
+        diagnostics
+    }
+
+    /// Imports of a file that belong to a cycle, with the chain of files
+    /// leading back to it.
+    fn get_import_cycles(&self, file_index: NodeIndex) -> Vec<(Range, Vec<Url>)> {
+        let Some(component) = self
+            .import_cycles
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| self.find_import_cycles())
+            .get(&file_index)
+            .cloned()
+        else {
+            return vec![];
+        };
+
+        self.file_graph
+            .edges_directed(file_index, EdgeDirection::Outgoing)
+            .filter(|edge| component.contains(&edge.target()))
+            .map(|edge| {
+                let chain = std::iter::once(file_index)
+                    .chain(self.find_import_chain(edge.target(), file_index, &component))
+                    .map(|index| self.file_graph.node_weight(index).unwrap().uri.clone())
+                    .collect();
+
+                (edge.weight().range, chain)
+            })
+            .collect()
+    }
+
+    /// Files of each import cycle, a file importing itself being one.
+    fn find_import_cycles(&self) -> HashMap<NodeIndex, Arc<[NodeIndex]>> {
+        let mut cycles = HashMap::new();
+
+        for component in petgraph::algo::tarjan_scc(&self.file_graph) {
+            if component.len() == 1
+                && self
+                    .file_graph
+                    .find_edge(component[0], component[0])
+                    .is_none()
+            {
+                continue;
+            }
+
+            let component: Arc<[NodeIndex]> = component.into();
+            for file_index in component.iter() {
+                cycles.insert(*file_index, component.clone());
+            }
+        }
+
+        cycles
+    }
+
+    fn invalidate_import_cycles(&mut self) {
+        *self.import_cycles.get_mut().unwrap() = None;
+    }
+
+    /// Shortest chain of imports from `from` to `to`, both included, through
+    /// files of `component`.
+    fn find_import_chain(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        component: &[NodeIndex],
+    ) -> Vec<NodeIndex> {
+        let mut previous: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(index) = queue.pop_front() {
+            if index == to {
+                break;
+            }
+
+            for next in self
+                .file_graph
+                .neighbors_directed(index, EdgeDirection::Outgoing)
+            {
+                if next != from && component.contains(&next) && !previous.contains_key(&next) {
+                    previous.insert(next, index);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut chain = vec![to];
+        while let Some(index) = previous.get(chain.last().unwrap()) {
+            chain.push(*index);
+        }
+        chain.reverse();
+
+        chain
+    }
+
+    /// Import statements of a file whose imported file has no symbol linked
+    /// from it.
+    fn get_unused_imports(&self, file_index: NodeIndex) -> Vec<Range> {
+        let file = self.file_graph.node_weight(file_index).unwrap();
+        let used_files: HashSet<NodeIndex> = file
+            .ast_manager
+            .lock()
+            .unwrap()
+            .visit_root()
+            .get_descendants()
+            .iter()
+            .filter_map(|node| node.get().linked_symbol.as_ref()?.get_file_id())
             .collect();
 
-        for (i, s) in imported_symbols.into_iter().enumerate() {
-            if let Some(range) = unlinked_symbols.get(&s.name) {
-                let symbol_id = SymbolId::new(Some(imported_file_index), scope_id, i);
+        self.file_graph
+            .edges_directed(file_index, EdgeDirection::Outgoing)
+            .filter(|edge| !used_files.contains(&edge.target()))
+            .map(|edge| edge.weight().range)
+            .collect()
+    }
 
-                file.ast_manager
-                    .lock()
-                    .unwrap()
-                    .link_symbol(symbol_id.clone(), *range);
-                let mut imported_st = imported_file.symbol_table_manager.lock().unwrap();
-                let symbol = imported_st.get_symbol_mut(symbol_id).unwrap();
-                symbol.add_usage(Usage::new_external(file_index, *range));
+    /// Resolves the imports of a file again, after files it imports were
+    /// created or deleted.
+    fn relink_imports(&mut self, file_index: NodeIndex) {
+        let imported_files: Vec<NodeIndex> = self
+            .file_graph
+            .neighbors_directed(file_index, EdgeDirection::Outgoing)
+            .collect();
+
+        for imported_file_index in imported_files {
+            self.unlink_file(file_index, imported_file_index);
+        }
+        super::features::diagnostics::ImportErrors::clear(&self.file_graph[file_index].uri);
+
+        self.clear_outgoing_edges(file_index);
+        self.link_imports(file_index);
+    }
+
+    /// Reads a file from disk again. Returns the urls of the files that
+    /// need fresh diagnostics.
+    fn reload_file(&mut self, url: &Url) -> Vec<Url> {
+        let Ok(content) = url
+            .to_file_path()
+            .and_then(|path| fs::read_to_string(path).map_err(|_| ()))
+        else {
+            return vec![];
+        };
+        if self.get_file(url).unwrap().source_code == content {
+            return vec![];
+        }
+
+        let mut updated = self.update_file(
+            url,
+            None,
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: content,
+            }],
+        );
+        updated.push(url.clone());
+
+        updated
+    }
+
+    /// Files with an import that was searched at `url` and not found or read.
+    fn get_importing_files(&self, url: &Url) -> Vec<NodeIndex> {
+        let mut importing_files: Vec<NodeIndex> = self
+            .unresolved_imports
+            .get(url)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        importing_files.sort();
+
+        importing_files
+    }
+
+    fn remove_unresolved_imports(&mut self, filter: impl Fn(NodeIndex) -> bool) {
+        self.unresolved_imports.retain(|_, importing_files| {
+            importing_files.retain(|file_index| !filter(*file_index));
+            !importing_files.is_empty()
+        });
+    }
+
+    /// Removes the files that are neither open, nor in the roots, nor
+    /// imported by such a file, directly or not. Returns their urls.
+    fn remove_unused_files(&mut self) -> Vec<Url> {
+        let mut used: HashSet<NodeIndex> = HashSet::new();
+        let mut queue: Vec<NodeIndex> = self
+            .open_files
+            .union(&self.workspace_files)
+            .copied()
+            .collect();
+        while let Some(file_index) = queue.pop() {
+            if used.insert(file_index) {
+                queue.extend(
+                    self.file_graph
+                        .neighbors_directed(file_index, EdgeDirection::Outgoing),
+                );
             }
         }
+
+        let unused: Vec<NodeIndex> = self
+            .file_graph
+            .node_indices()
+            .filter(|file_index| !used.contains(file_index))
+            .collect();
+
+        if !unused.is_empty() {
+            self.invalidate_import_cycles();
+            self.remove_unresolved_imports(|file_index| !used.contains(&file_index));
+        }
+
+        // Files importing an unused file are unused too, so only the usages
+        // recorded in the imported files have to be removed
+        for file_index in &unused {
+            self.clear_outgoing_edges(*file_index);
+        }
+
+        unused
+            .into_iter()
+            .filter_map(|file_index| {
+                let file = self.file_graph.remove_node(file_index)?;
+                self.url_node_map.remove(&file.uri);
+                super::features::diagnostics::ImportErrors::clear(&file.uri);
+
+                Some(file.uri)
+            })
+            .collect()
     }
 
     fn clear_outgoing_edges(&mut self, file_index: NodeIndex) {
+        self.invalidate_import_cycles();
         let outgoing_edges: Vec<_> = self
             .file_graph
             .edges_directed(file_index, EdgeDirection::Outgoing)
-            .map(|edge| edge.id())
+            .map(|edge| (edge.id(), edge.target()))
             .collect();
 
-        for id in outgoing_edges {
+        for (id, imported_file_index) in outgoing_edges {
+            self.file_graph
+                .node_weight(imported_file_index)
+                .unwrap()
+                .symbol_table_manager
+                .lock()
+                .unwrap()
+                .remove_external_usages(file_index);
             self.file_graph.remove_edge(id);
         }
     }
 
+    fn get_external_highlights(&self, file: &File) -> Vec<(Range, HighlightType)> {
+        let ast = file.ast_manager.lock().unwrap();
+
+        ast.visit_root()
+            .get_descendants()
+            .iter()
+            .filter_map(|node| {
+                let symbol_id = node.get().linked_symbol.clone()?;
+                let other_file = self.file_graph.node_weight(symbol_id.get_file_id()?)?;
+
+                let st = other_file.symbol_table_manager.lock().unwrap();
+                let symbol = st.get_symbol(symbol_id)?;
+                let symbol_def = other_file.language_def.get_symbol_def(&symbol.kind)?;
+
+                Some((node.get().range, symbol_def.highlight_type.clone()))
+            })
+            .collect()
+    }
+
+    /// Definition and usages of a symbol linked from `url`. Usages without a
+    /// file are in the file of the definition.
+    fn get_symbol_locations(
+        &self,
+        url: &Url,
+        symbol_id: &SymbolId,
+    ) -> Option<(Location, Vec<Location>)> {
+        let definition_url = match symbol_id.get_file_id() {
+            Some(file_index) => self.file_graph.node_weight(file_index)?.uri.clone(),
+            None => url.clone(),
+        };
+
+        let symbol = self
+            .get_file(&definition_url)?
+            .symbol_table_manager
+            .lock()
+            .unwrap()
+            .get_symbol(symbol_id.clone())?
+            .clone();
+
+        let usages = symbol
+            .usages
+            .iter()
+            .filter_map(|usage| {
+                let uri = match usage.file_id {
+                    Some(file_index) => self.file_graph.node_weight(file_index)?.uri.clone(),
+                    None => definition_url.clone(),
+                };
+
+                Some(Location::new(uri, usage.range))
+            })
+            .collect();
+
+        Some((Location::new(definition_url, symbol.def_range), usages))
+    }
+
+    /// Files only reached through library imports, opened files have no
+    /// incoming edge.
+    fn is_library_file(&self, file_index: NodeIndex) -> bool {
+        let mut imports = self
+            .file_graph
+            .edges_directed(file_index, EdgeDirection::Incoming)
+            .peekable();
+
+        imports.peek().is_some()
+            && imports.all(|edge| matches!(edge.weight().import, Import::Library))
+    }
+
     fn is_local_import(&self, file_index: NodeIndex, imported_file_index: NodeIndex) -> bool {
         let edge_index = self
             .file_graph
             .find_edge(file_index, imported_file_index)
             .unwrap();
 
-        let import_type = self.file_graph.edge_weight(edge_index).unwrap();
+        let import_edge = self.file_graph.edge_weight(edge_index).unwrap();
+
+        matches!(import_edge.import, Import::Local)
+    }
+}
 
-        matches!(import_type, Import::Local)
+/// Symbol linked from the object of a member usage, the usage or the member
+/// usage before it.
+fn get_member_object(arena: &Arena<Node>, member_id: NodeId) -> Option<SymbolId> {
+    let object_id = arena.get(member_id)?.previous_sibling()?;
+    let object = arena.get(object_id)?.get();
+
+    match object.symbol {
+        language_def::Symbol::Usage => object.linked_symbol.clone(),
+        language_def::Symbol::Expression => {
+            let member_id = object_id.children(arena).find(|id| {
+                matches!(
+                    arena.get(*id).unwrap().get().symbol,
+                    language_def::Symbol::MemberUsage
+                )
+            })?;
+            arena.get(member_id)?.get().linked_symbol.clone()
+        }
+        _ => None,
     }
 }
 
+/// Reports an import of `url` at `range` that could not be linked.
+fn add_import_error(url: &Url, range: Range, message: String) {
+    super::features::diagnostics::ImportErrors::add_error(
+        url.clone(),
+        Diagnostic::new_simple(range, message),
+    );
+}
+
 impl FileManagement for Workspace {
     fn get_file(&self, url: &Url) -> Option<&File> {
         let index = self.url_node_map.get(url)?;
@@ -198,55 +1135,183 @@ impl FileManagement for Workspace {
         self.file_graph.node_weight_mut(*index)
     }
 
-    fn add_file(&mut self, url: Url, content: &str) {
-        self.add_file(&url, content);
+    fn open_file(&mut self, url: Url, content: &str, version: i32) -> Vec<Url> {
+        let dependents = match self.url_node_map.get(&url) {
+            Some(_) if self.get_file(&url).unwrap().source_code == content => vec![],
+            Some(_) => self.update_file(
+                &url,
+                Some(version),
+                vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: content.to_string(),
+                }],
+            ),
+            None => {
+                self.add_file(&url, content);
+                vec![]
+            }
+        };
+
+        if let Some(file_index) = self.url_node_map.get(&url) {
+            self.open_files.insert(*file_index);
+            let file = &mut self.file_graph[*file_index];
+            file.version = Some(version);
+
+            // Compared with the disk once, saves and edits keep it up to date
+            let is_saved = url
+                .to_file_path()
+                .ok()
+                .and_then(|path| fs::read_to_string(path).ok())
+                .is_some_and(|content| content == file.source_code);
+            file.saved_version = is_saved.then_some(version);
+        }
+
+        dependents
+    }
+
+    fn close_file(&mut self, url: &Url) -> (Vec<Url>, Vec<Url>) {
+        let Some(file_index) = self.url_node_map.get(url).copied() else {
+            return (vec![], vec![]);
+        };
+        self.open_files.remove(&file_index);
+        self.file_graph[file_index].version = None;
+        self.file_graph[file_index].saved_version = None;
+
+        let removed = self.remove_unused_files();
+        if !self.url_node_map.contains_key(url) {
+            return (removed, vec![]);
+        }
+
+        // Still imported, the editor may have left unsaved changes
+        let updated = self.reload_file(url);
+
+        (removed, updated)
     }
 
-    fn update_file(&mut self, url: &Url, changes: Vec<TextDocumentContentChangeEvent>) {
+    fn update_file(
+        &mut self,
+        url: &Url,
+        version: Option<i32>,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Vec<Url> {
+        // Files without a registered language are not added
+        let Some(file_index) = self.url_node_map.get(url).copied() else {
+            return vec![];
+        };
         super::features::diagnostics::ImportErrors::clear(url);
-        let file_index = *self.url_node_map.get(url).unwrap();
         self.clear_outgoing_edges(file_index);
+        let links = self.get_dependent_links(file_index);
 
         let file = self.get_file_mut(url).unwrap();
 
         file.update(changes);
+        file.version = version;
+        file.saved_version = None;
 
-        for path in file.get_import_paths() {
-            match path {
-                Ok((import_type, path)) => {
-                    let imported_file_url = Url::from_file_path(path.clone()).unwrap();
+        self.link_imports(file_index);
+        self.relink_dependents(file_index, links)
+    }
 
-                    let maybe_imported_file_index = if let Some(imported_file_index) =
-                        self.url_node_map.get(&imported_file_url)
-                    {
-                        self.file_graph
-                            .add_edge(file_index, *imported_file_index, import_type);
-                        Some(*imported_file_index)
-                    } else {
-                        let content = fs::read_to_string(path).unwrap();
-                        let imported_file_index = self.add_file(&imported_file_url, &content);
-                        if let Some(i) = imported_file_index {
-                            self.file_graph.add_edge(file_index, i, import_type);
-                            Some(i)
-                        } else {
-                            None
-                        }
-                    };
+    fn apply_disk_change(&mut self, url: &Url, change: FileChangeType) -> (Vec<Url>, Vec<Url>) {
+        let file_index = self.url_node_map.get(url).copied();
+        if file_index.is_some_and(|file_index| self.open_files.contains(&file_index)) {
+            return (vec![], vec![]);
+        }
 
-                    if let Some(imported_file_index) = maybe_imported_file_index {
-                        self.link_imported_symbols(file_index, imported_file_index);
+        match (file_index, change) {
+            (Some(file_index), FileChangeType::DELETED) => {
+                self.workspace_files.remove(&file_index);
+                let dependents: Vec<NodeIndex> = self
+                    .file_graph
+                    .neighbors_directed(file_index, EdgeDirection::Incoming)
+                    .filter(|dependent| *dependent != file_index)
+                    .collect();
+                for dependent in &dependents {
+                    self.relink_imports(*dependent);
+                }
+
+                let removed = self.remove_unused_files();
+                let updated = dependents
+                    .into_iter()
+                    .filter_map(|dependent| {
+                        Some(self.file_graph.node_weight(dependent)?.uri.clone())
+                    })
+                    .collect();
+
+                (removed, updated)
+            }
+            (Some(_), _) => (vec![], self.reload_file(url)),
+            // Files that failed to find or read it can import it now
+            (None, FileChangeType::CREATED | FileChangeType::CHANGED) => {
+                if self.is_workspace_file(url) {
+                    if let Some(file_index) = url
+                        .to_file_path()
+                        .ok()
+                        .and_then(|path| fs::read_to_string(path).ok())
+                        .and_then(|content| self.add_file(url, &content))
+                    {
+                        self.workspace_files.insert(file_index);
                     }
                 }
-                Err(range) => {
-                    super::features::diagnostics::ImportErrors::add_error(
-                        url.clone(),
-                        Diagnostic::new_simple(range, String::from("File could not be found.")),
-                    );
+
+                let importing_files = self.get_importing_files(url);
+                for file_index in &importing_files {
+                    self.relink_imports(*file_index);
                 }
+
+                let updated = importing_files
+                    .into_iter()
+                    .map(|file_index| self.file_graph.node_weight(file_index).unwrap().uri.clone())
+                    .collect();
+
+                (vec![], updated)
             }
+            (None, _) => (vec![], vec![]),
         }
+    }
 
-        debug!("File graph:\n{:?}", Dot::with_config(&self.file_graph, &[]));
+    fn mark_saved(&mut self, url: &Url) {
+        if let Some(file) = self.get_file_mut(url) {
+            file.saved_version = file.version;
+        }
+    }
+
+    fn get_dirty_files(&self) -> Vec<&File> {
+        self.open_files
+            .iter()
+            .map(|file_index| &self.file_graph[*file_index])
+            .filter(|file| file.saved_version != file.version)
+            .collect()
+    }
+
+    fn rebuild_files(&mut self) {
+        // Symbol ids do not survive the rebuild, unlike stable ids
+        let links: Vec<_> = self
+            .file_graph
+            .node_indices()
+            .flat_map(|file_index| self.get_dependent_links(file_index))
+            .collect();
+
+        for file in self.file_graph.node_weights_mut() {
+            super::features::diagnostics::ImportErrors::clear(&file.uri);
+            if let Some(language) = self.registry.get_for_url(&file.uri) {
+                file.rebuild(&language);
+            }
+        }
+
+        // Every file has to be rebuilt before linking, since links point into
+        // the symbol tables of the imported files.
+        self.file_graph.clear_edges();
+        self.restore_links(links);
+        let file_indices: Vec<NodeIndex> = self.file_graph.node_indices().collect();
+        for file_index in file_indices {
+            self.link_imports(file_index);
+        }
+    }
+
+    fn get_urls(&self) -> Vec<Url> {
+        self.url_node_map.keys().cloned().collect()
     }
 }
 
@@ -289,71 +1354,105 @@ impl LanguageActions for Workspace {
         })
     }
 
+    fn get_document_symbols(&self, url: &Url) -> Option<Vec<DocumentSymbol>> {
+        Some(self.get_file(url)?.get_document_symbols())
+    }
+
+    fn get_workspace_symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        // Symbols are only cloned once they make the cut
+        let mut matches: Vec<(i64, bool, String, NodeIndex, SymbolId)> = vec![];
+
+        for file_index in self.file_graph.node_indices() {
+            let file = self.file_graph.node_weight(file_index).unwrap();
+            let is_library = self.is_library_file(file_index);
+
+            let st = file.symbol_table_manager.lock().unwrap();
+            for (name, symbol_id) in st.get_symbol_names() {
+                if let Some(score) = utils::fuzzy_score(query, name) {
+                    matches.push((
+                        score,
+                        is_library,
+                        name.clone(),
+                        file_index,
+                        symbol_id.clone(),
+                    ));
+                }
+            }
+        }
+
+        // Best matches first, local files before libraries
+        matches.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then(a.1.cmp(&b.1))
+                .then_with(|| a.2.cmp(&b.2))
+        });
+        matches.truncate(MAX_WORKSPACE_SYMBOLS);
+
+        matches
+            .into_iter()
+            .filter_map(|(_, _, name, file_index, symbol_id)| {
+                let file = self.file_graph.node_weight(file_index)?;
+                let st = file.symbol_table_manager.lock().unwrap();
+                let symbol = st.get_symbol(symbol_id)?;
+
+                let kind = file
+                    .language_def
+                    .get_symbol_def(&symbol.kind)
+                    .map_or(SymbolKind::VARIABLE, |symbol_def| {
+                        symbol_def.get_symbol_kind()
+                    });
+
+                #[allow(deprecated)] // `SymbolInformation::deprecated` has to be set
+                Some(SymbolInformation {
+                    name,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(file.uri.clone(), symbol.def_range),
+                    container_name: None,
+                })
+            })
+            .collect()
+    }
+
+    fn get_references(
+        &self,
+        url: &Url,
+        symbol_position: Position,
+        include_declaration: bool,
+    ) -> Option<Vec<Location>> {
+        let symbol_id = self.get_file(url)?.get_symbol_id_at_pos(symbol_position)?;
+        let (definition, usages) = self.get_symbol_locations(url, &symbol_id)?;
+
+        Some(if include_declaration {
+            std::iter::once(definition).chain(usages).collect()
+        } else {
+            usages
+        })
+    }
+
     fn rename_symbol(
         &mut self,
         url: &Url,
         symbol_position: Position,
         new_name: String,
     ) -> Option<WorkspaceEdit> {
-        let file = self.get_file_mut(url).unwrap();
-
-        let symbol_id = file
-            .ast_manager
-            .lock()
-            .unwrap()
-            .visit_root()
-            .get_node_at_position(symbol_position)?
-            .get()
-            .linked_symbol
-            .clone()?;
+        let symbol_id = self.get_file(url)?.get_symbol_id_at_pos(symbol_position)?;
 
-        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
-        let symbol = if let Some(file_id) = symbol_id.file_id {
+        if let Some(file_id) = symbol_id.file_id {
             if !self.is_local_import(*self.url_node_map.get(url)?, file_id) {
                 // You should not edit files that are not local to the project
                 return None;
             }
+        }
 
-            let file = self.file_graph.node_weight(file_id).unwrap();
-            let s = file
-                .symbol_table_manager
-                .lock()
-                .unwrap()
-                .get_symbol(symbol_id.clone())?
-                .clone();
-            changes.insert(
-                file.uri.clone(),
-                vec![TextEdit::new(s.def_range, new_name.clone())],
-            );
-            s
-        } else {
-            let s = file
-                .symbol_table_manager
-                .lock()
-                .unwrap()
-                .get_symbol(symbol_id.clone())?
-                .clone();
-            changes.insert(
-                url.clone(),
-                vec![TextEdit::new(s.def_range, new_name.clone())],
-            );
-            s
-        };
+        let (definition, usages) = self.get_symbol_locations(url, &symbol_id)?;
+        debug!("{:?}", usages);
 
-        debug!("{:?}", symbol.usages);
-        for usage in symbol.usages {
-            if let Some(file_id) = usage.file_id {
-                let file_url = &self.file_graph.node_weight(file_id).unwrap().uri;
-                let file_edits = changes.entry(file_url.clone()).or_default();
-                file_edits.push(TextEdit::new(usage.range, new_name.clone()));
-            } else if let Some(file_id) = symbol_id.file_id {
-                let file_url = &self.file_graph.node_weight(file_id).unwrap().uri;
-                let file_edits = changes.entry(file_url.clone()).or_default();
-                file_edits.push(TextEdit::new(usage.range, new_name.clone()));
-            } else {
-                let file_edits = changes.entry(url.clone()).or_default();
-                file_edits.push(TextEdit::new(usage.range, new_name.clone()));
-            }
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for location in std::iter::once(definition).chain(usages) {
+            let file_edits = changes.entry(location.uri).or_default();
+            file_edits.push(TextEdit::new(location.range, new_name.clone()));
         }
 
         Some(WorkspaceEdit::new(changes))
@@ -362,7 +1461,10 @@ impl LanguageActions for Workspace {
     fn get_semantic_tokens(&self, url: &Url) -> Option<SemanticTokensResult> {
         let file = self.get_file(url)?;
 
-        Some(file.get_semantic_tokens())
+        Some(file.get_semantic_tokens(
+            self.registry.get_semantic_token_types(),
+            self.get_external_highlights(file),
+        ))
     }
 
     fn get_completion(
@@ -435,11 +1537,67 @@ impl LanguageActions for Workspace {
         }
     }
 
+    fn get_code_lenses(&self, url: &Url) -> Vec<CodeLens> {
+        let Some(file) = self.get_file(url) else {
+            return vec![];
+        };
+        let (symbols, scope_id) = file
+            .symbol_table_manager
+            .lock()
+            .unwrap()
+            .get_symbols_at_root();
+
+        // The symbol is sent as a stable id, the file may be edited before
+        // the lens is resolved
+        symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(i, symbol)| {
+                let id = self.get_stable_symbol_id(url, &SymbolId::new(None, scope_id, i))?;
+
+                Some(CodeLens {
+                    range: symbol.def_range,
+                    command: None,
+                    data: serde_json::to_value(id).ok(),
+                })
+            })
+            .collect()
+    }
+
+    fn resolve_code_lens(&self, mut code_lens: CodeLens) -> CodeLens {
+        let Some(id) = code_lens
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<StableSymbolId>(data).ok())
+        else {
+            return code_lens;
+        };
+        let Some((_, symbol_id)) = self.resolve_stable_symbol_id(&id) else {
+            return code_lens;
+        };
+        let Some((_, usages)) = self.get_symbol_locations(&id.uri, &symbol_id) else {
+            return code_lens;
+        };
+
+        code_lens.command = Some(Command {
+            title: match usages.len() {
+                1 => String::from("1 reference"),
+                count => format!("{count} references"),
+            },
+            command: String::new(),
+            arguments: None,
+        });
+
+        code_lens
+    }
+
     fn get_quick_diagnostics(&self, url: &Url) -> Vec<Diagnostic> {
         let maybe_file = self.get_file(url);
 
         if let Some(file) = maybe_file {
-            file.get_quick_diagnostics()
+            let mut diagnostics = file.get_quick_diagnostics();
+            diagnostics.append(&mut self.get_workspace_diagnostics(file));
+            diagnostics
         } else {
             vec![]
         }
@@ -449,9 +1607,583 @@ impl LanguageActions for Workspace {
         let maybe_file = self.get_file(url);
 
         if let Some(file) = maybe_file {
-            file.get_full_diagnostics()
+            let mut diagnostics = file.get_full_diagnostics();
+            diagnostics.append(&mut self.get_workspace_diagnostics(file));
+            diagnostics
         } else {
             vec![]
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use tower_lsp::lsp_types::{
+        Diagnostic, DiagnosticSeverity, FileChangeType, HoverContents, Location, MarkedString,
+        NumberOrString, Position, Range, TextDocumentContentChangeEvent, Url,
+    };
+
+    use super::{File, FileManagement, LanguageActions, Workspace};
+    use crate::language_def::{LanguageDefinition, LanguageRegistry};
+    use crate::setup::LanguageSetup;
+    use crate::RulesTranslator;
+
+    const RULES: &str = include_str!("../../../../tests/fixtures/javascript.ron");
+
+    fn workspace() -> Workspace {
+        workspace_with_rules(RULES)
+    }
+
+    fn workspace_with_rules(rules: &str) -> Workspace {
+        let language = tree_sitter_javascript::language();
+        let (language_def, _) = LanguageDefinition::load(rules, language).unwrap();
+
+        let mut registry = LanguageRegistry::default();
+        registry
+            .register(&LanguageSetup {
+                language_def: rules.to_string(),
+                rules_path: None,
+                treesitter_language: language,
+                translator: Arc::new(RulesTranslator::new(Arc::new(language_def))),
+            })
+            .unwrap();
+
+        Workspace::new(Arc::new(registry))
+    }
+
+    /// Writes the files in an empty directory named after the test.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> Vec<Url> {
+        let directory = std::env::temp_dir().join(format!("lever-{test}"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+
+        files
+            .iter()
+            .map(|(name, content)| {
+                let path = directory.join(name);
+                fs::write(&path, content).unwrap();
+                Url::from_file_path(path).unwrap()
+            })
+            .collect()
+    }
+
+    /// Start of the first occurrence of `pattern`.
+    fn position(source: &str, pattern: &str) -> Position {
+        let offset = source.find(pattern).unwrap();
+        let line = source[..offset].matches('\n').count();
+        let character = offset - source[..offset].rfind('\n').map_or(0, |i| i + 1);
+
+        Position::new(line as u32, character as u32)
+    }
+
+    fn range(source: &str, pattern: &str) -> Range {
+        let start = position(source, pattern);
+        Range::new(
+            start,
+            Position::new(start.line, start.character + pattern.len() as u32),
+        )
+    }
+
+    /// Import errors of a file, with their messages.
+    fn import_errors(workspace: &Workspace, url: &Url) -> Vec<(Range, String)> {
+        workspace
+            .get_full_diagnostics(url)
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.message.starts_with("File could not be")
+                    || diagnostic.message.starts_with("No language")
+            })
+            .map(|diagnostic| (diagnostic.range, diagnostic.message))
+            .collect()
+    }
+
+    /// Ranges of the diagnostics with a code.
+    fn ranges(diagnostics: &[Diagnostic], code: &str) -> Vec<Range> {
+        let code = Some(NumberOrString::String(code.to_string()));
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.code == code)
+            .map(|diagnostic| diagnostic.range)
+            .collect()
+    }
+
+    #[test]
+    fn test_imported_member_usages() {
+        let point = "class Point {\n    x = 1;\n}\n";
+        let main = "import \"./point.js\";\nconst p = new Point();\np.x;\np.z;\n";
+        let urls = write_files(
+            "imported-members",
+            &[("point.js", point), ("main.js", main)],
+        );
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[1].clone(), main, 1);
+
+        let x = Location::new(urls[0].clone(), range(point, "x"));
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "x;")),
+            Some(x.clone())
+        );
+        assert_eq!(
+            workspace.get_references(&urls[0], x.range.start, false),
+            Some(vec![Location::new(urls[1].clone(), range(main, "x"))])
+        );
+        assert_eq!(
+            workspace.get_hover_info(&urls[1], position(main, "p =")),
+            Some(HoverContents::Scalar(MarkedString::String(String::from(
+                "p: Point"
+            ))))
+        );
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "z;")),
+            None
+        );
+
+        // Links are kept once the imported file is edited
+        workspace.open_file(urls[0].clone(), point, 1);
+        workspace.update_file(
+            &urls[0],
+            Some(2),
+            vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, 0), Position::new(0, 0))),
+                range_length: None,
+                text: String::from("\n"),
+            }],
+        );
+        let mut x = x;
+        x.range.start.line += 1;
+        x.range.end.line += 1;
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "x;")),
+            Some(x)
+        );
+    }
+
+    #[test]
+    fn test_duplicates_and_shadowing() {
+        // Functions allow overloading, variables do not
+        let main = "class Point {\n    x = 1;\n}\nconst x = 1;\nconst x = 2;\nfunction f() {\n    const x = 3;\n}\nfunction f() {}\n";
+        let urls = write_files("duplicates", &[("main.js", main)]);
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[0].clone(), main, 1);
+        let diagnostics = workspace.get_full_diagnostics(&urls[0]);
+
+        let x = |line| Range::new(Position::new(line, 6), Position::new(line, 7));
+        assert_eq!(ranges(&diagnostics, "duplicate"), vec![x(4)]);
+        assert_eq!(
+            ranges(&diagnostics, "shadowing"),
+            vec![Range::new(Position::new(6, 10), Position::new(6, 11))]
+        );
+    }
+
+    #[test]
+    fn test_unused_symbols_and_imports() {
+        let point = "class Point {}\n";
+        let main = "import \"./point.js\";\nconst a = 1;\nfunction f() {\n    const b = 2;\n}\n";
+        let urls = write_files("unused", &[("point.js", point), ("main.js", main)]);
+
+        let unused = |policy: Option<&str>| {
+            let rules = match policy {
+                Some(policy) => RULES.replace(
+                    "(name: \"Variable\", completion_type: Variable, highlight_type: Variable)",
+                    &format!("(name: \"Variable\", completion_type: Variable, highlight_type: Variable, unused: {policy})"),
+                ),
+                None => RULES.to_string(),
+            };
+            let mut workspace = workspace_with_rules(&rules);
+            workspace.open_file(urls[1].clone(), main, 1);
+
+            ranges(&workspace.get_full_diagnostics(&urls[1]), "unused")
+        };
+
+        let import = range(main, "./point.js");
+        let a = range(main, "a");
+        let b = range(main, "b");
+        // Top-level definitions may be used by importing files by default
+        assert_eq!(unused(None), vec![b, import]);
+        assert_eq!(unused(Some("AllowTopLevel")), vec![b, import]);
+        assert_eq!(unused(Some("Report")), vec![a, b, import]);
+        assert_eq!(unused(Some("Allow")), vec![import]);
+    }
+
+    #[test]
+    fn test_import_cycles() {
+        let files = [
+            ("a.js", "import \"./b.js\";\n"),
+            ("b.js", "import \"./c.js\";\n"),
+            ("c.js", "import \"./a.js\";\n"),
+        ];
+        let urls = write_files("import-cycles", &files);
+
+        let cycles = |rules: &str| {
+            let mut workspace = workspace_with_rules(rules);
+            workspace.open_file(urls[0].clone(), files[0].1, 1);
+
+            urls.iter()
+                .flat_map(|url| workspace.get_full_diagnostics(url))
+                .filter(|diagnostic| {
+                    diagnostic.code == Some(NumberOrString::String(String::from("import-cycle")))
+                })
+                .map(|diagnostic| (diagnostic.range, diagnostic.severity, diagnostic.message))
+                .collect::<Vec<_>>()
+        };
+        let expected = |severity| {
+            vec![
+                (
+                    range(files[0].1, "./b.js"),
+                    Some(severity),
+                    String::from("import cycle: a.js → b.js → c.js → a.js"),
+                ),
+                (
+                    range(files[1].1, "./c.js"),
+                    Some(severity),
+                    String::from("import cycle: b.js → c.js → a.js → b.js"),
+                ),
+                (
+                    range(files[2].1, "./a.js"),
+                    Some(severity),
+                    String::from("import cycle: c.js → a.js → b.js → c.js"),
+                ),
+            ]
+        };
+
+        assert_eq!(cycles(RULES), expected(DiagnosticSeverity::WARNING));
+        let forbidden = RULES.replace(
+            "library_paths: (env_variables: [], linux: [], windows: [], macos: []),",
+            "library_paths: (env_variables: [], linux: [], windows: [], macos: []),\n        forbid_cyclic_imports: true,",
+        );
+        assert_eq!(cycles(&forbidden), expected(DiagnosticSeverity::ERROR));
+    }
+
+    #[test]
+    fn test_relink_dependents() {
+        let lib = "function first() {}\nfunction second() {}\n";
+        let main = "import \"./lib.js\";\nfirst();\nsecond();\n";
+        let urls = write_files("relink-dependents", &[("lib.js", lib), ("main.js", main)]);
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[1].clone(), main, 1);
+        workspace.open_file(urls[0].clone(), lib, 1);
+
+        // Adds a line above the definitions and renames `second`
+        let lib = "\nfunction first() {}\nfunction renamed() {}\n";
+        let dependents = workspace.update_file(
+            &urls[0],
+            Some(2),
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: lib.to_string(),
+            }],
+        );
+        assert_eq!(dependents, vec![urls[1].clone()]);
+
+        let first = Location::new(urls[0].clone(), range(lib, "first"));
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "first")),
+            Some(first.clone())
+        );
+        assert_eq!(
+            workspace.get_references(&urls[0], first.range.start, false),
+            Some(vec![Location::new(urls[1].clone(), range(main, "first"))])
+        );
+
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "second")),
+            None
+        );
+        assert_eq!(
+            workspace.get_references(&urls[0], position(lib, "renamed"), false),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_stable_links() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("stable-links", &[("lib.js", lib), ("main.js", main)]);
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[1].clone(), main, 1);
+        workspace.open_file(urls[0].clone(), lib, 1);
+
+        let linked_id = |workspace: &Workspace| {
+            let symbol_id = workspace
+                .get_file(&urls[1])
+                .unwrap()
+                .get_symbol_id_at_pos(position(main, "helper"))
+                .unwrap();
+            workspace.get_stable_symbol_id(&urls[1], &symbol_id)
+        };
+        let id = linked_id(&workspace);
+        assert!(id.is_some());
+        let code_lenses = workspace.get_code_lenses(&urls[0]);
+        assert_eq!(code_lenses.len(), 1);
+
+        // Adds a definition above the linked one
+        let added = "function added() {}\n";
+        workspace.update_file(
+            &urls[0],
+            Some(2),
+            vec![TextDocumentContentChangeEvent {
+                range: Some(Range::default()),
+                range_length: None,
+                text: added.to_string(),
+            }],
+        );
+        let lib = format!("{added}{lib}");
+
+        assert_eq!(linked_id(&workspace), id);
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "helper")),
+            Some(Location::new(urls[0].clone(), range(&lib, "helper")))
+        );
+
+        let code_lens = workspace.resolve_code_lens(code_lenses[0].clone());
+        assert_eq!(
+            code_lens.command.map(|command| command.title),
+            Some(String::from("1 reference"))
+        );
+
+        workspace.rebuild_files();
+        assert_eq!(linked_id(&workspace), id);
+        assert_eq!(
+            workspace.get_references(&urls[0], position(&lib, "helper"), false),
+            Some(vec![Location::new(urls[1].clone(), range(main, "helper"))])
+        );
+    }
+
+    #[test]
+    fn test_close_evicts_unused_files() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("close-evicts", &[("lib.js", lib), ("main.js", main)]);
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[1].clone(), main, 1);
+        assert!(workspace.get_file(&urls[0]).is_some());
+
+        // The imported file goes with the only file importing it
+        let (mut removed, updated) = workspace.close_file(&urls[1]);
+        removed.sort();
+        assert_eq!(removed, urls);
+        assert!(updated.is_empty());
+        assert!(workspace.get_urls().is_empty());
+    }
+
+    #[test]
+    fn test_close_keeps_imported_files() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("close-keeps", &[("lib.js", lib), ("main.js", main)]);
+
+        let mut workspace = workspace();
+        let language = workspace.registry.get_for_url(&urls[1]).unwrap();
+        workspace.add_workspace_files(vec![File::new(urls[1].clone(), main, &language)]);
+
+        workspace.open_file(urls[0].clone(), lib, 1);
+        let (removed, updated) = workspace.close_file(&urls[0]);
+        assert!(removed.is_empty());
+        assert!(updated.is_empty());
+
+        assert_eq!(workspace.get_file(&urls[0]).unwrap().version, None);
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "helper")),
+            Some(Location::new(urls[0].clone(), range(lib, "helper")))
+        );
+    }
+
+    #[test]
+    fn test_close_reverts_unsaved_changes() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("close-reverts", &[("lib.js", lib), ("main.js", main)]);
+
+        let mut workspace = workspace();
+        let language = workspace.registry.get_for_url(&urls[1]).unwrap();
+        workspace.add_workspace_files(vec![File::new(urls[1].clone(), main, &language)]);
+
+        // Renames the definition without saving
+        let unsaved = "function renamed() {}\n";
+        workspace.open_file(urls[0].clone(), lib, 1);
+        workspace.update_file(
+            &urls[0],
+            Some(2),
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: unsaved.to_string(),
+            }],
+        );
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "helper")),
+            None
+        );
+
+        let (removed, mut updated) = workspace.close_file(&urls[0]);
+        updated.sort();
+        assert!(removed.is_empty());
+        assert_eq!(updated, urls);
+
+        assert_eq!(workspace.get_file(&urls[0]).unwrap().source_code, lib);
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "helper")),
+            Some(Location::new(urls[0].clone(), range(lib, "helper")))
+        );
+    }
+
+    #[test]
+    fn test_unresolved_imports() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("unresolved-imports", &[("main.js", main)]);
+        let lib_path = urls[0].to_file_path().unwrap().with_file_name("lib.js");
+        let lib_url = Url::from_file_path(&lib_path).unwrap();
+
+        // A folder cannot be read as the imported file
+        fs::create_dir(&lib_path).unwrap();
+        let mut workspace = workspace();
+        workspace.open_file(urls[0].clone(), main, 1);
+        let errors = import_errors(&workspace, &urls[0]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, range(main, "./lib.js"));
+        assert!(errors[0].1.starts_with("File could not be read: "));
+
+        fs::remove_dir(&lib_path).unwrap();
+        workspace.relink_imports(workspace.url_node_map[&urls[0]]);
+        assert_eq!(
+            import_errors(&workspace, &urls[0]),
+            vec![(
+                range(main, "./lib.js"),
+                String::from("File could not be found.")
+            )]
+        );
+
+        fs::write(&lib_path, lib).unwrap();
+        let (removed, updated) = workspace.apply_disk_change(&lib_url, FileChangeType::CREATED);
+        assert!(removed.is_empty());
+        assert_eq!(updated, vec![urls[0].clone()]);
+        assert!(import_errors(&workspace, &urls[0]).is_empty());
+        assert_eq!(
+            workspace.get_definition_location(&urls[0], position(main, "helper")),
+            Some(Location::new(lib_url, range(lib, "helper")))
+        );
+    }
+
+    #[test]
+    fn test_unreadable_imports() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("unreadable-imports", &[("main.js", main)]);
+        let lib_path = urls[0].to_file_path().unwrap().with_file_name("lib.js");
+        let lib_url = Url::from_file_path(&lib_path).unwrap();
+
+        fs::write(&lib_path, [0xff, 0xfe]).unwrap();
+        let mut workspace = workspace();
+        workspace.open_file(urls[0].clone(), main, 1);
+        let errors = import_errors(&workspace, &urls[0]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].1.starts_with("File could not be read: "));
+
+        // Linked once it is readable
+        fs::write(&lib_path, lib).unwrap();
+        let (_, updated) = workspace.apply_disk_change(&lib_url, FileChangeType::CHANGED);
+        assert_eq!(updated, vec![urls[0].clone()]);
+        assert!(import_errors(&workspace, &urls[0]).is_empty());
+        assert_eq!(
+            workspace.get_definition_location(&urls[0], position(main, "helper")),
+            Some(Location::new(lib_url, range(lib, "helper")))
+        );
+    }
+
+    #[test]
+    fn test_imports_without_language() {
+        let main = "import \"./notes.txt\";\n";
+        let urls = write_files(
+            "imports-without-language",
+            &[("main.js", main), ("notes.txt", "helper\n")],
+        );
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[0].clone(), main, 1);
+        assert_eq!(
+            import_errors(&workspace, &urls[0]),
+            vec![(
+                range(main, "./notes.txt"),
+                String::from("No language is registered for this file.")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_workspace_files_in_batches() {
+        let lib = "function helper() {}\n";
+        let main = "import \"./lib.js\";\nhelper();\n";
+        let urls = write_files("workspace-batches", &[("lib.js", lib), ("main.js", main)]);
+
+        let mut workspace = workspace();
+        let language = workspace.registry.get_for_url(&urls[0]).unwrap();
+        let mut files: Vec<File> = urls
+            .iter()
+            .zip([lib, main])
+            .map(|(url, content)| File::new(url.clone(), content, &language))
+            .collect();
+
+        // The imported file comes in the next batch, it is read from disk
+        // in the meantime
+        let lib_file = files.remove(0);
+        workspace.add_workspace_files(files);
+        assert_eq!(
+            workspace.get_definition_location(&urls[1], position(main, "helper")),
+            Some(Location::new(urls[0].clone(), range(lib, "helper")))
+        );
+
+        workspace.add_workspace_files(vec![lib_file]);
+        assert_eq!(workspace.workspace_files.len(), 2);
+        assert_eq!(
+            workspace.get_references(&urls[0], position(lib, "helper"), false),
+            Some(vec![Location::new(urls[1].clone(), range(main, "helper"))])
+        );
+    }
+
+    #[test]
+    fn test_dirty_files() {
+        let lib = "function helper() {}\n";
+        let urls = write_files("dirty-files", &[("lib.js", lib)]);
+        let dirty = |workspace: &Workspace| {
+            workspace
+                .get_dirty_files()
+                .into_iter()
+                .map(|file| file.uri.clone())
+                .collect::<Vec<_>>()
+        };
+        let edit = |workspace: &mut Workspace, version| {
+            workspace.update_file(
+                &urls[0],
+                Some(version),
+                vec![TextDocumentContentChangeEvent {
+                    range: Some(Range::default()),
+                    range_length: None,
+                    text: String::from("\n"),
+                }],
+            );
+        };
+
+        let mut workspace = workspace();
+        workspace.open_file(urls[0].clone(), lib, 1);
+        assert!(dirty(&workspace).is_empty());
+
+        edit(&mut workspace, 2);
+        assert_eq!(dirty(&workspace), urls);
+        workspace.mark_saved(&urls[0]);
+        assert!(dirty(&workspace).is_empty());
+
+        edit(&mut workspace, 3);
+        assert_eq!(dirty(&workspace), urls);
+    }
+}
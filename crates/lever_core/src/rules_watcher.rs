@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tower_lsp::lsp_types::{Diagnostic, MessageType, Url};
+use tower_lsp::Client;
+
+use crate::language_def::LanguageRegistry;
+use crate::project::workspace::{FileManagement, LanguageActions, Workspace};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls the rules file and swaps the language definition when it changes.
+/// An invalid edit is reported on the rules file and the previous definition
+/// stays active.
+pub struct RulesWatcher {
+    path: PathBuf,
+    language_index: usize,
+    registry: Arc<LanguageRegistry>,
+    client: Client,
+    workspace: Arc<RwLock<Workspace>>,
+    last_modified: Option<SystemTime>,
+    last_source: String,
+}
+
+impl RulesWatcher {
+    pub fn spawn(
+        path: PathBuf,
+        language_index: usize,
+        registry: Arc<LanguageRegistry>,
+        client: Client,
+        workspace: Arc<RwLock<Workspace>>,
+    ) {
+        let watcher = RulesWatcher::new(path, language_index, registry, client, workspace);
+
+        tokio::spawn(watcher.run());
+    }
+
+    /// Starts from the rules loaded at startup, so the file is only reloaded
+    /// once it differs from them.
+    fn new(
+        path: PathBuf,
+        language_index: usize,
+        registry: Arc<LanguageRegistry>,
+        client: Client,
+        workspace: Arc<RwLock<Workspace>>,
+    ) -> RulesWatcher {
+        let last_source = registry.languages()[language_index].source();
+        // Left unset when the file changed since, to reload it on the first
+        // check
+        let last_modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .filter(|_| fs::read_to_string(&path).is_ok_and(|source| source == last_source));
+
+        RulesWatcher {
+            path,
+            language_index,
+            registry,
+            client,
+            workspace,
+            last_modified,
+            last_source,
+        }
+    }
+
+    async fn run(mut self) {
+        info!("Watching rules file: {}", self.path.display());
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            self.check().await;
+        }
+    }
+
+    /// Reloads the rules if the file changed since the last check. Returns
+    /// whether the new rules are active, `None` when nothing changed.
+    async fn check(&mut self) -> Option<bool> {
+        let modified = tokio::fs::metadata(&self.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+        self.last_modified = Some(modified);
+
+        let source = tokio::fs::read_to_string(&self.path).await.ok()?;
+        if self.last_source == source {
+            return None;
+        }
+
+        let reloaded = self.reload(&source).await;
+        self.last_source = source;
+
+        Some(reloaded)
+    }
+
+    /// Returns whether the rules were valid, the workspace is only rebuilt
+    /// then.
+    async fn reload(&self, source: &str) -> bool {
+        let Ok(rules_uri) = Url::from_file_path(&self.path) else {
+            return false;
+        };
+
+        match self.registry.reload(self.language_index, source) {
+            Ok(report) => {
+                info!("Rules reloaded from {}", self.path.display());
+                self.client
+                    .publish_diagnostics(rules_uri, report.to_diagnostics(source), None)
+                    .await;
+
+                let diagnostics: Vec<(Url, Vec<Diagnostic>)> = {
+                    let mut workspace = self.workspace.write().unwrap();
+                    workspace.rebuild_files();
+
+                    workspace
+                        .get_urls()
+                        .into_iter()
+                        .map(|url| {
+                            let diagnostics = workspace.get_full_diagnostics(&url);
+                            (url, diagnostics)
+                        })
+                        .collect()
+                };
+
+                for (url, diagnostics) in diagnostics {
+                    self.client
+                        .publish_diagnostics(url, diagnostics, None)
+                        .await;
+                }
+
+                if let Err(err) = self.client.semantic_tokens_refresh().await {
+                    debug!("Semantic tokens refresh failed: {err}");
+                }
+
+                true
+            }
+            Err(report) => {
+                warn!("Rules reload failed, keeping previous rules:\n{report}");
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        "Rules file has errors, keeping the previous rules.",
+                    )
+                    .await;
+                self.client
+                    .publish_diagnostics(rules_uri, report.to_diagnostics(source), None)
+                    .await;
+
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, RwLock};
+    use std::time::{Duration, SystemTime};
+
+    use tower_lsp::LspService;
+
+    use super::RulesWatcher;
+    use crate::language_def::{LanguageDefinition, LanguageRegistry};
+    use crate::language_server::Backend;
+    use crate::project::workspace::Workspace;
+    use crate::setup::LanguageSetup;
+    use crate::RulesTranslator;
+
+    const RULES: &str = include_str!("../../../tests/fixtures/javascript.ron");
+
+    /// Watcher of a rules file written in an empty directory named after the
+    /// test.
+    fn watcher(test: &str) -> (RulesWatcher, Arc<LanguageRegistry>) {
+        let directory = std::env::temp_dir().join(format!("lever-{test}"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("rules.ron");
+        fs::write(&path, RULES).unwrap();
+
+        let language = tree_sitter_javascript::language();
+        let (language_def, _) = LanguageDefinition::load(RULES, language).unwrap();
+        let mut registry = LanguageRegistry::default();
+        registry
+            .register(&LanguageSetup {
+                language_def: RULES.to_string(),
+                rules_path: Some(path.clone()),
+                treesitter_language: language,
+                translator: Arc::new(RulesTranslator::new(Arc::new(language_def))),
+            })
+            .unwrap();
+        let registry = Arc::new(registry);
+
+        // Messages are not sent before the client initialized the server
+        let mut client = None;
+        let _ = LspService::new(|lsp_client| {
+            client = Some(lsp_client.clone());
+            Backend::init(lsp_client, registry.clone())
+        });
+        let workspace = Arc::new(RwLock::new(Workspace::new(registry.clone())));
+
+        let watcher = RulesWatcher::new(path, 0, registry.clone(), client.unwrap(), workspace);
+        (watcher, registry)
+    }
+
+    /// Writes the rules with a modification time of their own, since edits
+    /// may be closer than the precision of the file system.
+    fn edit(path: &Path, source: &str, seconds: u64) {
+        fs::write(path, source).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_rules() {
+        let (mut watcher, registry) = watcher("rules-unchanged");
+        let definition = registry.languages()[0].definition();
+
+        assert_eq!(watcher.check().await, None);
+
+        // Saved again without changes
+        edit(&watcher.path, RULES, 1);
+        assert_eq!(watcher.check().await, None);
+        assert!(Arc::ptr_eq(
+            &definition,
+            &registry.languages()[0].definition()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_changed_rules() {
+        let (mut watcher, registry) = watcher("rules-changed");
+        let definition = registry.languages()[0].definition();
+
+        let rules = format!("{RULES}\n// Edited\n");
+        edit(&watcher.path, &rules, 1);
+        assert_eq!(watcher.check().await, Some(true));
+        assert!(!Arc::ptr_eq(
+            &definition,
+            &registry.languages()[0].definition()
+        ));
+        assert_eq!(registry.languages()[0].source(), rules);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_rules() {
+        let (mut watcher, registry) = watcher("rules-invalid");
+        let definition = registry.languages()[0].definition();
+
+        edit(&watcher.path, "(language: ", 1);
+        assert_eq!(watcher.check().await, Some(false));
+        assert!(Arc::ptr_eq(
+            &definition,
+            &registry.languages()[0].definition()
+        ));
+        assert_eq!(registry.languages()[0].source(), RULES);
+    }
+}
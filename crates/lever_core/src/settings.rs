@@ -1,16 +1,39 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
 use serde_json::Value;
 
-#[derive(Debug, Default)]
-pub struct Settings {}
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Warn when a definition hides a symbol of an enclosing scope
+    pub warn_outer_shadowing: bool,
+    /// Warn when a definition hides a symbol of an imported file
+    pub warn_import_shadowing: bool,
+    /// Searched for library imports before the directories of the rules,
+    /// relative paths start from the workspace folder
+    pub library_paths: Vec<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            warn_outer_shadowing: true,
+            warn_import_shadowing: true,
+            library_paths: vec![],
+        }
+    }
+}
 
 impl Settings {
     pub fn parse(value: Value) -> Settings {
-        if let Value::Object(_map) = value {
-            Settings {}
+        if let Value::Object(_) = value {
+            serde_json::from_value(value).unwrap_or_else(|err| {
+                warn!("Invalid settings, using the defaults: {err}");
+                Settings::default()
+            })
         } else {
-            Settings {
-                // ..Default::default()
-            }
+            Settings::default()
         }
     }
 }
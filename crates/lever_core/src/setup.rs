@@ -1,5 +1,38 @@
-pub struct Setup {
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub struct LanguageSetup {
     pub language_def: String,
+    /// When set, the rules are read from this file instead of `language_def`
+    /// and reloaded whenever it changes.
+    pub rules_path: Option<PathBuf>,
     pub treesitter_language: tree_sitter::Language,
-    pub translator: &'static dyn crate::Translator,
+    /// Translator generated from `language_def`, it is only used while the
+    /// active rules are `language_def`, other rules are interpreted.
+    pub translator: Arc<dyn crate::Translator>,
+}
+
+/// Every language hosted by the server. Files are matched to a language
+/// with the `file_extensions` of its rules, files that match none are
+/// ignored.
+pub struct Setup {
+    pub languages: Vec<LanguageSetup>,
+}
+
+impl LanguageSetup {
+    pub(crate) fn read_rules(&self) -> String {
+        self.rules_path
+            .as_ref()
+            .and_then(|path| match std::fs::read_to_string(path) {
+                Ok(language_def) => Some(language_def),
+                Err(err) => {
+                    eprintln!(
+                        "Could not read rules from {}, using the embedded rules: {err}",
+                        path.display()
+                    );
+                    None
+                }
+            })
+            .unwrap_or_else(|| self.language_def.clone())
+    }
 }
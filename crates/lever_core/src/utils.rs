@@ -24,6 +24,21 @@ pub const fn ts_range_to_lsp_range(range: tree_sitter::Range) -> tower_lsp::lsp_
     }
 }
 
+/// Moves a position of text starting at `from` along with that text, now
+/// starting at `to`.
+pub fn move_position(position: Position, from: Position, to: Position) -> Position {
+    let character = if position.line == from.line {
+        position.character + to.character - from.character
+    } else {
+        position.character
+    };
+
+    Position {
+        line: position.line + to.line - from.line,
+        character,
+    }
+}
+
 pub fn pos_to_byte(pos: Position, text: &str) -> usize {
     let mut total_bytes = 0;
     let lines = &text.lines().collect::<Vec<&str>>()[..(pos.line as usize)];
@@ -69,11 +84,54 @@ pub fn find_lib(paths: &[PathBuf], file_name: &str) -> Option<PathBuf> {
     })
 }
 
+/// Scores `candidate` against a fuzzy `pattern` whose characters must all
+/// appear in order, ignoring case. Consecutive characters and characters
+/// starting a word score higher. Returns `None` when it does not match.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut pattern_chars = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut score: i64 = 0;
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for c in candidate.chars() {
+        let Some(&expected) = pattern_chars.peek() else {
+            break;
+        };
+
+        let matched = c.to_lowercase().eq(std::iter::once(expected));
+        if matched {
+            pattern_chars.next();
+            score += 1;
+
+            if previous_matched {
+                score += 4;
+            }
+            match previous {
+                None => score += 8,
+                Some(p) if !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()) => {
+                    score += 6;
+                }
+                _ => {}
+            }
+        }
+
+        previous_matched = matched;
+        previous = Some(c);
+    }
+
+    if pattern_chars.peek().is_some() {
+        return None;
+    }
+
+    // Prefers the closest match among candidates matching the same way
+    Some(score * 16 - candidate.chars().count() as i64)
+}
+
 #[cfg(test)]
 mod tests {
     use tower_lsp::lsp_types::Position;
 
-    use super::pos_to_byte;
+    use super::{fuzzy_score, pos_to_byte};
 
     #[test]
     fn test_pos_to_byte() {
@@ -100,4 +158,17 @@ mod tests {
             5
         );
     }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("xyz", "get_symbol"), None);
+        assert_eq!(fuzzy_score("symbolget", "get_symbol"), None);
+        assert!(fuzzy_score("", "anything").is_some());
+
+        let word_starts = fuzzy_score("gs", "get_symbol").unwrap();
+        assert!(word_starts > fuzzy_score("gs", "digits").unwrap());
+        assert!(fuzzy_score("gs", "getSymbol").unwrap() > fuzzy_score("gs", "gauss").unwrap());
+        assert!(fuzzy_score("symbol", "Symbol").unwrap() > word_starts);
+        assert!(fuzzy_score("sym", "Symbol").unwrap() > fuzzy_score("sym", "SymbolTable").unwrap());
+    }
 }
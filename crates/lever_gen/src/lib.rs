@@ -1,10 +1,18 @@
+mod typed_ast;
+
 use lever_core::Child;
 use lever_core::DirectOrRule;
+use lever_core::Import;
 use lever_core::Rule;
+use lever_core::Symbol;
 use lever_core::TreesitterNodeQuery;
+use lever_core::ValidationReport;
 use quote::format_ident;
 use quote::quote;
+use quote::quote_spanned;
+use std::collections::HashSet;
 use std::fs;
+use std::path::PathBuf;
 use syn::parse_macro_input;
 
 use lever_core::LanguageDefinition;
@@ -16,13 +24,19 @@ pub fn start_server(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     quote! {
         #[tokio::main]
         async fn main() {
-            let language_def = include_str!("../language_def/rules.ron");
-            let translator = Box::new(rules_translator!("language_def/rules.ron"));
+            let language_def = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/language_def/rules.ron"));
+            let translator = std::sync::Arc::new(rules_translator!("language_def/rules.ron"));
 
             let setup = Setup {
-                language_def: language_def.to_string(),
-                treesitter_language: #input,
-                translator: Box::leak(translator),
+                languages: vec![LanguageSetup {
+                    language_def: language_def.to_string(),
+                    // Rules are reloaded from disk while developing the language server
+                    rules_path: cfg!(debug_assertions).then(|| {
+                        std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/language_def/rules.ron"))
+                    }),
+                    treesitter_language: #input,
+                    translator,
+                }],
             };
 
             start_server(&setup).await
@@ -31,52 +45,130 @@ pub fn start_server(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     .into()
 }
 
+/// Generates a `Translator` from a rules file, the path being relative to
+/// the crate's manifest. Invalid rules are reported as compile errors and
+/// their warnings as deprecation warnings.
 #[proc_macro]
 pub fn rules_translator(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as syn::LitStr);
 
-    let file_path = input.value();
-    let file_contents = fs::read_to_string(file_path).expect("Could not read file");
+    let (language_def, report, file_path) = match load_rules(&input) {
+        Ok(rules) => rules,
+        Err(errors) => return errors,
+    };
 
-    let language_def: LanguageDefinition = ron::from_str(&file_contents).unwrap();
+    // Proc macros cannot emit warnings on stable, deprecated items are used instead
+    let warnings = report.warnings().map(|diag| {
+        let note = format!("{}: {diag}", input.value());
+        quote_spanned!(input.span()=>
+            {
+                #[deprecated(note = #note)]
+                fn rules_warning() {}
+                rules_warning();
+            }
+        )
+    });
 
-    let mut rule_parsers = vec![];
-    for rule in language_def.ast_rules {
-        rule_parsers.push(gen_parse_rule(&rule));
-    }
+    // Rebuilds the translator whenever the rules change
+    let file_path = file_path.to_string_lossy();
+
+    // Only the first rule of a name is used, like `LanguageDefinition::rule_with_name`
+    let mut rule_names = HashSet::new();
+    let rule_parsers = language_def
+        .ast_rules
+        .iter()
+        .filter(|rule| rule_names.insert(&rule.node_name))
+        .map(|rule| gen_parse_rule(rule, &language_def.global_ast_rules))
+        .collect::<Vec<proc_macro2::TokenStream>>();
 
+    // Mirrors `RulesTranslator`, the ASTs of both translators must be identical
     let output = quote! {
         {
-            fn child_by_kind<'a>(
-                node: &'a tree_sitter::Node,
-                kind: &'a str,
+            const _: &str = include_str!(#file_path);
+            #(#warnings)*
+
+            fn find_named_child<'a>(
+                node: &tree_sitter::Node<'a>,
+                matches: impl Fn(usize, &tree_sitter::Node<'a>) -> bool,
             ) -> Option<tree_sitter::Node<'a>> {
                 let mut cursor = node.walk();
+                let found = node
+                    .children(&mut cursor)
+                    .enumerate()
+                    .filter(|(_, child)| child.is_named())
+                    .find(|(i, child)| matches(*i, child))
+                    .map(|(_, child)| child);
+
+                found
+            }
 
-                for child in node.children(&mut cursor) {
-                    if child.kind() == kind {
-                        return Some(child);
-                    }
-                }
-
-                None
+            fn query_captures<'a>(
+                node: &tree_sitter::Node<'a>,
+                query: &tree_sitter::Query,
+                source_code: &str,
+            ) -> Vec<tree_sitter::Node<'a>> {
+                let capture_names = query.capture_names();
+                let mut cursor = tree_sitter::QueryCursor::new();
+
+                let mut nodes: Vec<tree_sitter::Node> = cursor
+                    .matches(query, *node, source_code.as_bytes())
+                    .flat_map(|query_match| query_match.captures.iter())
+                    .filter(|capture| !capture_names[capture.index as usize].starts_with('_'))
+                    .map(|capture| capture.node)
+                    .collect();
+
+                nodes.sort_by_key(|node| {
+                    (node.start_byte(), std::cmp::Reverse(node.end_byte()), node.id())
+                });
+                nodes.dedup_by_key(|node| node.id());
+                nodes
             }
 
-            struct GeneratedRuleTranslator{
-                arena: indextree::Arena<Node>,
-                source_code: String,
-            };
+            struct GeneratedRuleTranslator;
 
             impl Translator for GeneratedRuleTranslator {
-                fn translate(&mut self, source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast {
-                    let root_id = self.parse_Root(&syntax_tree.root_node()).unwrap();
-                    self.source_code = source_code.to_string();
-                    Ast::initialize(self.arena.clone(), root_id) // TODO: Remove clone?
+                fn translate(&self, source_code: &str, syntax_tree: tree_sitter::Tree) -> Ast {
+                    let mut translation = GeneratedTranslation {
+                        arena: indextree::Arena::new(),
+                        source_code,
+                        reusable: ::lever_framework::__private::ReusableNodes::default(),
+                    };
+                    let root_id = translation.parse_Root(&syntax_tree.root_node());
+
+                    translation
+                        .reusable
+                        .finish(translation.arena, root_id, syntax_tree)
+                }
+
+                fn retranslate(
+                    &self,
+                    source_code: &str,
+                    syntax_tree: tree_sitter::Tree,
+                    ast: &mut Ast,
+                ) {
+                    let (arena, reusable) =
+                        ::lever_framework::__private::ReusableNodes::take(ast, &syntax_tree);
+                    let mut translation = GeneratedTranslation {
+                        arena,
+                        source_code,
+                        reusable,
+                    };
+                    let root_id = translation.parse_Root(&syntax_tree.root_node());
+
+                    *ast = translation
+                        .reusable
+                        .finish(translation.arena, root_id, syntax_tree);
                 }
             }
 
-            impl GeneratedRuleTranslator {
+            struct GeneratedTranslation<'a> {
+                arena: indextree::Arena<Node>,
+                source_code: &'a str,
+                reusable: ::lever_framework::__private::ReusableNodes,
+            }
 
+            #[allow(non_snake_case)]
+            impl GeneratedTranslation<'_> {
                 fn new_node(
                     &mut self,
                     kind: &str,
@@ -84,26 +176,22 @@ pub fn rules_translator(input: proc_macro::TokenStream) -> proc_macro::TokenStre
                     symbol: Symbol,
                     import: Import,
                     semantic_token_type: Option<HighlightType>,
-                ) -> NodeId {
+                ) -> indextree::NodeId {
                     self.arena.new_node(Node::new(
                         NodeKind::Node(kind.to_string()),
                         syntax_node,
-                        &self.source_code,
+                        self.source_code,
                         symbol,
                         import,
                         semantic_token_type,
                     ))
                 }
 
-                fn new_error_node(
-                    &mut self,
-                    syntax_node: &tree_sitter::Node,
-                    message: Option<String>,
-                ) -> NodeId {
+                fn new_error_node(&mut self, syntax_node: &tree_sitter::Node) -> indextree::NodeId {
                     self.arena.new_node(Node::new(
-                        NodeKind::Error(message),
+                        NodeKind::Error(None),
                         syntax_node,
-                        &self.source_code,
+                        self.source_code,
                         Symbol::None,
                         Import::None,
                         None,
@@ -113,41 +201,238 @@ pub fn rules_translator(input: proc_macro::TokenStream) -> proc_macro::TokenStre
                 #(#rule_parsers)*
             }
 
-            GeneratedRuleTranslator {
-                arena: indextree::Arena::new(),
-                source_code: String::new()
-            }
+            GeneratedRuleTranslator
         }
     };
 
     output.into()
 }
 
-fn gen_parse_rule(rule: &Rule) -> proc_macro2::TokenStream {
+/// Generates typed wrappers over `VisitNode` from a rules file: a
+/// `<Rule>Node` struct per rule and per `Direct` node, with a method per
+/// kind of child. Kinds whose structs or methods would share a name are
+/// reported as compile errors.
+#[proc_macro]
+pub fn typed_ast(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as syn::LitStr);
+
+    // Warnings are already reported by `rules_translator!`
+    let (language_def, _, file_path) = match load_rules(&input) {
+        Ok(rules) => rules,
+        Err(errors) => return errors,
+    };
+
+    let file_path = file_path.to_string_lossy();
+    let wrappers = match typed_ast::gen_wrappers(&language_def) {
+        Ok(wrappers) => wrappers,
+        Err(collisions) => {
+            let errors = collisions.iter().map(|collision| {
+                syn::Error::new(input.span(), format!("{}: {collision}", input.value()))
+                    .to_compile_error()
+            });
+            return quote!(#(#errors)*).into();
+        }
+    };
+
+    quote!(
+        const _: &str = include_str!(#file_path);
+
+        #wrappers
+    )
+    .into()
+}
+
+/// Reads and validates a rules file relative to the crate's manifest,
+/// errors are returned as compile errors.
+fn load_rules(
+    input: &syn::LitStr,
+) -> Result<(LanguageDefinition, ValidationReport, PathBuf), proc_macro::TokenStream> {
+    let file_path = match std::env::var("CARGO_MANIFEST_DIR") {
+        Ok(manifest_dir) => PathBuf::from(manifest_dir).join(input.value()),
+        Err(_) => PathBuf::from(input.value()),
+    };
+    let file_contents = fs::read_to_string(&file_path).map_err(|err| {
+        proc_macro::TokenStream::from(
+            syn::Error::new(
+                input.span(),
+                format!("Could not read {}: {err}", file_path.display()),
+            )
+            .to_compile_error(),
+        )
+    })?;
+
+    let language_def =
+        LanguageDefinition::parse(&file_contents).map_err(|report| rules_errors(input, &report))?;
+    let report = language_def.validate(&file_contents);
+    if report.has_errors() {
+        return Err(rules_errors(input, &report));
+    }
+
+    Ok((language_def, report, file_path))
+}
+
+fn rules_errors(input: &syn::LitStr, report: &ValidationReport) -> proc_macro::TokenStream {
+    let errors = report.errors().map(|diag| {
+        syn::Error::new(input.span(), format!("{}: {diag}", input.value())).to_compile_error()
+    });
+
+    quote!(#(#errors)*).into()
+}
+
+fn gen_parse_rule(rule: &Rule, global_rules: &[Child]) -> proc_macro2::TokenStream {
     let fn_name = format_ident!("parse_{}", &rule.node_name);
     let kind = rule.node_name.clone();
+    let symbol = gen_symbol(&rule.symbol);
+    let import = gen_import(&rule.import);
 
     let children = rule
         .children
         .iter()
+        .chain(global_rules)
         .map(gen_child)
         .collect::<Vec<proc_macro2::TokenStream>>();
 
     quote!(
-        fn #fn_name (&mut self, node: &tree_sitter::Node) -> Option<indextree::NodeId> {
-            let node_id = self.new_node(#kind, node, Symbol::None, Import::None, None); // TODO:
-                                                                                           // Remove Nones
+        fn #fn_name (&mut self, node: &tree_sitter::Node) -> indextree::NodeId {
+            if let Some(node_id) = self.reusable.reuse(&mut self.arena, #kind, node) {
+                return node_id;
+            }
+
+            let mut cursor = node.walk();
+            let children: Vec<tree_sitter::Node> = node.children(&mut cursor).collect();
+
+            let node_id = self.new_node(#kind, node, #symbol, #import, None);
+            for error_node in children.iter().filter(|child| child.is_error()) {
+                node_id.append(self.new_error_node(error_node), &mut self.arena);
+            }
 
             #(#children)*
 
-            Some(node_id)
+            self.reusable.record(node, node_id);
+            node_id
         }
     )
 }
 
+fn gen_symbol(symbol: &Symbol) -> proc_macro2::TokenStream {
+    match symbol {
+        Symbol::Init {
+            kind,
+            name_node,
+            type_node,
+        } => {
+            let type_node = match type_node {
+                Some(type_node) => quote!(Some(String::from(#type_node))),
+                None => quote!(None),
+            };
+
+            quote!(Symbol::Init {
+                kind: String::from(#kind),
+                name_node: String::from(#name_node),
+                type_node: #type_node,
+            })
+        }
+        Symbol::Usage => quote!(Symbol::Usage),
+        Symbol::Field { name_node } => quote!(Symbol::Field {
+            name_node: String::from(#name_node),
+        }),
+        Symbol::Expression => quote!(Symbol::Expression),
+        Symbol::MemberUsage => quote!(Symbol::MemberUsage),
+        Symbol::None => quote!(Symbol::None),
+    }
+}
+
+fn gen_import(import: &Import) -> proc_macro2::TokenStream {
+    match import {
+        Import::Local => quote!(Import::Local),
+        Import::Library => quote!(Import::Library),
+        Import::None => quote!(Import::None),
+    }
+}
+
+/// Code appending the nodes selected by the query of `child` to `node_id`,
+/// `node` and its `children` being the syntax node of the current rule.
 fn gen_child(child: &Child) -> proc_macro2::TokenStream {
-    let query = gen_query(&child.query);
+    let append = gen_append(child);
+
+    match &child.query {
+        TreesitterNodeQuery::Kind(kind) => quote!(
+            for ts_node in children.iter().copied() {
+                if ts_node.kind() == #kind {
+                    let target_node = ts_node;
+                    #append
+                }
+            }
+        ),
+        TreesitterNodeQuery::Field(field) => quote!(
+            for (i, ts_node) in children.iter().copied().enumerate() {
+                if node.field_name_for_child(i as u32) == Some(#field) {
+                    let target_node = ts_node;
+                    #append
+                }
+            }
+        ),
+        TreesitterNodeQuery::Path(path) => {
+            // Paths starting with anything else never match
+            let (header, condition) = match path.first() {
+                Some(TreesitterNodeQuery::Kind(kind)) => (
+                    quote!(for ts_node in children.iter().copied()),
+                    quote!(ts_node.kind() == #kind),
+                ),
+                Some(TreesitterNodeQuery::Field(field)) => (
+                    quote!(for (i, ts_node) in children.iter().copied().enumerate()),
+                    quote!(node.field_name_for_child(i as u32) == Some(#field)),
+                ),
+                _ => return quote!(),
+            };
+
+            // Elements that are not found leave the target where it is
+            let steps = path.iter().skip(1).filter_map(|element| {
+                let matches = match element {
+                    TreesitterNodeQuery::Kind(kind) => quote!(|_, child| child.kind() == #kind),
+                    TreesitterNodeQuery::Field(field) => quote!(
+                        |i, _| target_node.field_name_for_child(i as u32) == Some(#field)
+                    ),
+                    _ => return None,
+                };
 
+                Some(quote!(
+                    if let Some(found) = find_named_child(&target_node, #matches) {
+                        target_node = found;
+                    }
+                ))
+            });
+
+            quote!(
+                #header {
+                    if #condition {
+                        #[allow(unused_mut)]
+                        let mut target_node = ts_node;
+                        #(#steps)*
+                        #append
+                    }
+                }
+            )
+        }
+        TreesitterNodeQuery::Query(pattern) => quote!(
+            {
+                static QUERY: std::sync::OnceLock<tree_sitter::Query> = std::sync::OnceLock::new();
+                let query = QUERY.get_or_init(|| {
+                    tree_sitter::Query::new(node.language(), #pattern).expect("Invalid query")
+                });
+
+                for ts_node in query_captures(node, query, self.source_code) {
+                    let target_node = ts_node;
+                    #append
+                }
+            }
+        ),
+    }
+}
+
+/// Code appending the translation of `target_node` to `node_id`, errors
+/// are reported on `ts_node`, the syntax node the query matched.
+fn gen_append(child: &Child) -> proc_macro2::TokenStream {
     match &child.rule {
         DirectOrRule::Direct(name) => {
             let highlight_type = if let Some(ht) = &child.highlight_type {
@@ -157,70 +442,28 @@ fn gen_child(child: &Child) -> proc_macro2::TokenStream {
             };
 
             quote!(
-                if let Some(ts_node) = #query {
-                    if ts_node.has_error() {
-                        node_id
-                            .append(self.new_error_node(&ts_node, None), &mut self.arena);
-                    }
-
-                    node_id.append(
-                        self.new_node(
-                            #name,
-                            &node,
-                            Symbol::None,
-                            Import::None,
-                            #highlight_type,
-                        ),
-                        &mut self.arena,
-                    );
+                if ts_node.has_error() {
+                    node_id.append(self.new_error_node(&ts_node), &mut self.arena);
                 }
+
+                node_id.append(
+                    self.new_node(
+                        #name,
+                        &target_node,
+                        Symbol::None,
+                        Import::None,
+                        #highlight_type,
+                    ),
+                    &mut self.arena,
+                );
             )
         }
         DirectOrRule::Rule(name) => {
             let fn_name = format_ident!("parse_{}", name);
 
             quote!(
-                if let Some(node) = #query {
-                    node_id.append(self.#fn_name(&node)?, &mut self.arena);
-                }
+                node_id.append(self.#fn_name(&target_node), &mut self.arena);
             )
         }
     }
 }
-
-fn gen_query(query: &TreesitterNodeQuery) -> proc_macro2::TokenStream {
-    match query {
-        TreesitterNodeQuery::Path(path) => {
-            if path.is_empty() {
-                panic!("Empty paths are not allowed.");
-            }
-
-            let mut parts = vec![];
-            parts.push(match path.first().unwrap() {
-                TreesitterNodeQuery::Path(_) => {
-                    unimplemented!("Nested paths are not supported.")
-                }
-                TreesitterNodeQuery::Kind(kind) => quote!(child_by_kind(node, #kind)),
-                TreesitterNodeQuery::Field(field) => quote!(node.child_by_field_name(#field)),
-            });
-
-            for query in path.iter().skip(1) {
-                parts.push(match query {
-                    TreesitterNodeQuery::Path(_) => {
-                        unimplemented!("Nested paths are not supported.")
-                    }
-                    TreesitterNodeQuery::Kind(kind) => quote!(
-                        .and_then(|n| child_by_kind(node, #kind))
-                    ),
-                    TreesitterNodeQuery::Field(field) => quote!(
-                        .and_then(|n| n.child_by_field_name(#field))
-                    ),
-                });
-            }
-
-            quote!(#(#parts)*)
-        }
-        TreesitterNodeQuery::Kind(kind) => quote!(child_by_kind(node, #kind)),
-        TreesitterNodeQuery::Field(field) => quote!(node.child_by_field_name(#field)),
-    }
-}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use lever_core::{Child, DirectOrRule, LanguageDefinition};
+use quote::{format_ident, quote};
+
+// Methods generated on every wrapper
+const BUILT_IN_METHODS: [&str; 2] = ["cast", "visit"];
+
+/// Wrappers of every kind, or the kinds whose generated names collide.
+pub fn gen_wrappers(
+    language_def: &LanguageDefinition,
+) -> Result<proc_macro2::TokenStream, Vec<String>> {
+    let mut kinds: Vec<&str> = vec![];
+    for rule in &language_def.ast_rules {
+        push_unique(&mut kinds, &rule.node_name);
+        for child in rule.children.iter().chain(&language_def.global_ast_rules) {
+            push_unique(&mut kinds, produced_kind(child));
+        }
+    }
+
+    let mut errors = vec![];
+    let mut structs: HashMap<String, &str> = HashMap::new();
+    for kind in &kinds {
+        let name = struct_name(kind).to_string();
+        if let Some(other) = structs.get(&name) {
+            errors.push(format!(
+                "kinds `{other}` and `{kind}` both generate the struct `{name}`"
+            ));
+        } else {
+            structs.insert(name, kind);
+        }
+    }
+
+    let wrappers: Vec<proc_macro2::TokenStream> = kinds
+        .iter()
+        .map(|kind| {
+            let children: Vec<&Child> = language_def
+                .rule_with_name(kind)
+                .map(|rule| {
+                    rule.children
+                        .iter()
+                        .chain(&language_def.global_ast_rules)
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            gen_wrapper(kind, &children, &mut errors)
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(quote!(#(#wrappers)*))
+    } else {
+        Err(errors)
+    }
+}
+
+fn gen_wrapper(
+    kind: &str,
+    children: &[&Child],
+    errors: &mut Vec<String>,
+) -> proc_macro2::TokenStream {
+    let wrapper_name = struct_name(kind);
+
+    let mut child_kinds: Vec<&str> = vec![];
+    for child in children {
+        push_unique(&mut child_kinds, produced_kind(child));
+    }
+
+    // Kind whose accessor has the name, `None` for the built-in methods
+    let mut methods: HashMap<String, Option<&str>> = BUILT_IN_METHODS
+        .iter()
+        .map(|method| (method.to_string(), None))
+        .collect();
+    for child_kind in &child_kinds {
+        for method in [
+            method_name(child_kind, ""),
+            method_name(child_kind, "_list"),
+        ] {
+            let method = method.to_string();
+            match methods.get(&method) {
+                Some(Some(other)) => errors.push(format!(
+                    "kinds `{other}` and `{child_kind}` both generate the method `{method}` of `{wrapper_name}`"
+                )),
+                Some(None) => errors.push(format!(
+                    "kind `{child_kind}` generates the method `{method}` of `{wrapper_name}`, which is built-in"
+                )),
+                None => {
+                    methods.insert(method, Some(child_kind));
+                }
+            }
+        }
+    }
+
+    let accessors = child_kinds.iter().map(|child_kind| {
+        let child_struct = struct_name(child_kind);
+        let method = method_name(child_kind, "");
+        let list_method = method_name(child_kind, "_list");
+
+        quote!(
+            pub fn #method(&self) -> Option<#child_struct<'a>> {
+                self.0.children().find_map(#child_struct::cast)
+            }
+
+            pub fn #list_method(&self) -> Vec<#child_struct<'a>> {
+                self.0.children().filter_map(#child_struct::cast).collect()
+            }
+        )
+    });
+
+    quote!(
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct #wrapper_name<'a>(::lever_framework::VisitNode<'a>);
+
+        impl<'a> #wrapper_name<'a> {
+            pub const KIND: &'static str = #kind;
+
+            pub fn cast(node: ::lever_framework::VisitNode<'a>) -> Option<Self> {
+                match &node.node().kind {
+                    ::lever_framework::NodeKind::Node(kind) if kind == Self::KIND => Some(Self(node)),
+                    _ => None,
+                }
+            }
+
+            pub const fn visit(&self) -> ::lever_framework::VisitNode<'a> {
+                self.0
+            }
+
+            #(#accessors)*
+        }
+
+        impl<'a> ::std::ops::Deref for #wrapper_name<'a> {
+            type Target = ::lever_framework::VisitNode<'a>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+    )
+}
+
+fn produced_kind(child: &Child) -> &str {
+    match &child.rule {
+        DirectOrRule::Direct(kind) | DirectOrRule::Rule(kind) => kind,
+    }
+}
+
+fn push_unique<'a>(kinds: &mut Vec<&'a str>, kind: &'a str) {
+    if !kinds.contains(&kind) {
+        kinds.push(kind);
+    }
+}
+
+fn struct_name(kind: &str) -> syn::Ident {
+    format_ident!("{}Node", sanitize(kind))
+}
+
+/// Snake case name of the accessor, `MessageBody` gives `message_body`.
+fn method_name(kind: &str, suffix: &str) -> syn::Ident {
+    let mut name = String::new();
+    for (i, c) in sanitize(kind).chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 && !name.ends_with('_') {
+                name.push('_');
+            }
+            name.extend(c.to_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name.push_str(suffix);
+
+    // Keywords such as `type` cannot be used as method names
+    if syn::parse_str::<syn::Ident>(&name).is_err() {
+        name.push('_');
+    }
+
+    format_ident!("{}", name)
+}
+
+fn sanitize(kind: &str) -> String {
+    kind.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(children: &str) -> LanguageDefinition {
+        LanguageDefinition::parse(&format!(
+            r#"(
+                language: (
+                    name: "Test",
+                    file_extensions: ["test"],
+                    library_paths: (env_variables: [], linux: [], windows: [], macos: []),
+                ),
+                keywords: [],
+                symbol_types: [],
+                global_ast_rules: [],
+                ast_rules: [
+                    Rule(node_name: "Root", children: [{children}]),
+                ],
+            )"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_name_collisions() {
+        let errors = gen_wrappers(&rules(
+            r#"(query: Kind("a"), rule: Direct("Visit")),
+               (query: Kind("b"), rule: Direct("Foo")),
+               (query: Kind("c"), rule: Direct("FooList")),
+               (query: Kind("d"), rule: Direct("foo-bar")),
+               (query: Kind("e"), rule: Direct("foo_bar"))"#,
+        ))
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "kinds `foo-bar` and `foo_bar` both generate the struct `foo_barNode`",
+                "kind `Visit` generates the method `visit` of `RootNode`, which is built-in",
+                "kinds `Foo` and `FooList` both generate the method `foo_list` of `RootNode`",
+                "kinds `foo-bar` and `foo_bar` both generate the method `foo_bar` of `RootNode`",
+                "kinds `foo-bar` and `foo_bar` both generate the method `foo_bar_list` of `RootNode`",
+            ]
+        );
+        assert!(gen_wrappers(&rules(r#"(query: Kind("a"), rule: Direct("Foo"))"#)).is_ok());
+    }
+}
@@ -1,9 +1,15 @@
-pub use lever_core::{Ast, HighlightType, Import, Node, NodeKind, Setup, Symbol, Translator};
-pub use lever_gen::{rules_translator, start_server};
+pub use lever_core::{
+    Ast, HighlightType, Import, LanguageSetup, Node, NodeKind, RulesTranslator, Setup, Symbol,
+    Translator, VisitNode, Visitable,
+};
+pub use lever_gen::{rules_translator, start_server, typed_ast};
 pub use {indextree, tokio, tree_sitter};
 
 pub use crate::indextree::NodeId;
 
+#[doc(hidden)]
+pub use lever_core::__private;
+
 pub async fn start_server(setup: &lever_core::Setup) {
     lever_core::start_server(setup).await
 }
@@ -0,0 +1,168 @@
+//! The translator generated by `rules_translator!` must build the same ASTs
+//! as `RulesTranslator`, since the server falls back to it when rules change.
+
+use std::sync::Arc;
+
+use lever_core::LanguageDefinition;
+use lever_framework::*;
+
+const RULES: &str = include_str!("fixtures/javascript.ron");
+
+const SAMPLES: &[&str] = &[
+    "",
+    r#"import { helper } from "./helper.js";
+
+// Adds two numbers
+function add(first, second) {
+    let sum = first + second;
+    return sum;
+}
+
+add(arg1, arg2);
+"#,
+    r#"const config = { name: "lever", nested: { depth: 2 }, "quoted": true };
+let empty = {};
+log(config, { inline: arg });
+"#,
+    r#"function broken( {
+    let = ;
+}
+let value = { a: 1,, b: };
+call(arg
+"#,
+];
+
+fn parse(source_code: &str) -> tree_sitter::Tree {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(tree_sitter_javascript::language())
+        .unwrap();
+
+    parser.parse(source_code, None).unwrap()
+}
+
+fn translate_both(source_code: &str) -> (Ast, Ast) {
+    let (language_def, _) =
+        LanguageDefinition::load(RULES, tree_sitter_javascript::language()).unwrap();
+    let interpreted = RulesTranslator::new(Arc::new(language_def));
+    let generated = rules_translator!("tests/fixtures/javascript.ron");
+
+    (
+        interpreted.translate(source_code, parse(source_code)),
+        generated.translate(source_code, parse(source_code)),
+    )
+}
+
+#[test]
+fn test_generated_translator_matches_rules_translator() {
+    for source_code in SAMPLES {
+        let (interpreted, generated) = translate_both(source_code);
+
+        assert_eq!(
+            format!("{interpreted:?}"),
+            format!("{generated:?}"),
+            "\nInterpreted:\n{interpreted}\nGenerated:\n{generated}"
+        );
+    }
+}
+
+#[test]
+fn test_query_captures() {
+    let (interpreted, _) = translate_both(SAMPLES[2]);
+
+    assert_eq!(
+        interpreted.get_debug_tree(),
+        "\
++-  Root
+   |-  Declaration
+   |  |-  Name
+   |  |-  Key
+   |  |-  Key
+   |  +-  Key
+   |-  Declaration
+   |  +-  Name
+   +-  Statement
+      |-  Usage
+      +-  Argument
+"
+    );
+}
+
+/// Nodes in document order, with their positions and contents.
+fn nodes(ast: &Ast) -> Vec<String> {
+    ast.visit_root()
+        .get_descendants()
+        .iter()
+        .map(|node| format!("{:?}", node.get()))
+        .collect()
+}
+
+#[test]
+fn test_retranslate_matches_translate() {
+    let (language_def, _) =
+        LanguageDefinition::load(RULES, tree_sitter_javascript::language()).unwrap();
+    let translators: Vec<Box<dyn Translator>> = vec![
+        Box::new(RulesTranslator::new(Arc::new(language_def))),
+        Box::new(rules_translator!("tests/fixtures/javascript.ron")),
+    ];
+    // Replaced range and text, applied in order
+    let edits = [
+        ((5, 4), (5, 4), "let other = sum;\n    "),
+        ((4, 8), (4, 11), "total"),
+        ((0, 0), (0, 0), "\n\n"),
+        ((5, 0), (9, 0), ""),
+        ((2, 0), (2, 0), "call(arg, { key: 1 }"),
+    ];
+
+    for translator in &translators {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(tree_sitter_javascript::language())
+            .unwrap();
+
+        let mut source_code = SAMPLES[1].to_string();
+        let mut tree = parser.parse(&source_code, None).unwrap();
+        let mut ast = translator.translate(&source_code, tree.clone());
+
+        for ((start_row, start_column), (end_row, end_column), text) in edits {
+            let byte = |source_code: &str, row: usize, column: usize| {
+                source_code
+                    .split_inclusive('\n')
+                    .take(row)
+                    .map(str::len)
+                    .sum::<usize>()
+                    + column
+            };
+            let start_byte = byte(&source_code, start_row, start_column);
+            let old_end_byte = byte(&source_code, end_row, end_column);
+            source_code.replace_range(start_byte..old_end_byte, text);
+
+            let lines: Vec<&str> = text.split('\n').collect();
+            let new_end_position = if lines.len() == 1 {
+                tree_sitter::Point::new(start_row, start_column + text.len())
+            } else {
+                tree_sitter::Point::new(start_row + lines.len() - 1, lines.last().unwrap().len())
+            };
+            let edit = tree_sitter::InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte: start_byte + text.len(),
+                start_position: tree_sitter::Point::new(start_row, start_column),
+                old_end_position: tree_sitter::Point::new(end_row, end_column),
+                new_end_position,
+            };
+            ast.edit(&edit);
+            tree.edit(&edit);
+            tree = parser.parse(&source_code, Some(&tree)).unwrap();
+
+            translator.retranslate(&source_code, tree.clone(), &mut ast);
+            let expected = translator.translate(&source_code, tree.clone());
+
+            assert_eq!(
+                nodes(&ast),
+                nodes(&expected),
+                "\nRetranslated:\n{ast}\nTranslated:\n{expected}"
+            );
+        }
+    }
+}
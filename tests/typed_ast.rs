@@ -0,0 +1,49 @@
+use lever_framework::*;
+
+mod javascript {
+    lever_framework::typed_ast!("tests/fixtures/javascript.ron");
+}
+
+use javascript::{FunctionNode, RootNode};
+
+const SOURCE: &str = r#"// Entry point
+function add(first, second) {
+    let sum = first + second;
+    return sum;
+}
+"#;
+
+fn translate(source_code: &str) -> Ast {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(tree_sitter_javascript::language())
+        .unwrap();
+
+    rules_translator!("tests/fixtures/javascript.ron")
+        .translate(source_code, parser.parse(source_code, None).unwrap())
+}
+
+#[test]
+fn test_typed_accessors() {
+    let ast = translate(SOURCE);
+    let root = RootNode::cast(ast.visit_root()).unwrap();
+
+    let function = root.function().unwrap();
+    assert_eq!(function.name().unwrap().get().content, "add");
+    assert_eq!(function.parameter().unwrap().get().content, "first");
+    assert_eq!(root.comment().unwrap().get().content, "// Entry point");
+
+    let body = function.body().unwrap();
+    let declaration = body.declaration().unwrap();
+    assert_eq!(declaration.name().unwrap().get().content, "sum");
+    assert_eq!(body.usage_list().len(), 1);
+    assert!(root.declaration_list().is_empty());
+}
+
+#[test]
+fn test_cast_checks_kind() {
+    let ast = translate(SOURCE);
+
+    assert!(FunctionNode::cast(ast.visit_root()).is_none());
+    assert_eq!(RootNode::KIND, "Root");
+}